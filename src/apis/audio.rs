@@ -1,29 +1,43 @@
-// Learn how to turn audio into text.
+// Learn how to turn audio into text or text into audio.
+// See: https://platform.openai.com/docs/api-reference/audio
 
 //! Audio API
 
+use std::fmt;
 use std::fs::File;
+use std::io::{self, Cursor, Read, Write};
+use std::ops::Range;
+use std::path::Path;
+use std::time::Duration;
 
 use crate::mpart::Mpart as Multipart;
+use mime::Mime;
 use serde::{Deserialize, Serialize};
 
-use crate::requests::Requests;
+use crate::requests::{to_json, Requests};
 use crate::*;
 
-use super::{AUDIO_TRANSCRIPTION_CREATE, AUDIO_TRANSLATIONS_CREATE};
+use super::{AUDIO_SPEECH_CREATE, AUDIO_TRANSCRIPTION_CREATE, AUDIO_TRANSLATIONS_CREATE};
+
+#[cfg(feature = "async")]
+use {
+	crate::async_requests::{read_text, AsyncRequests, FormData},
+	std::future::Future,
+};
 
 #[derive(Debug)]
 pub struct AudioBody {
 	/// The audio file to transcribe,
-	/// in one of these formats: mp3, mp4, mpeg, mpga, m4a, wav, or webm.
-	pub file: File,
+	/// in one of these formats: flac, mp3, mp4, mpeg, mpga, m4a, ogg, wav, or webm.
+	pub file: AudioInput,
 	/// ID of the model to use. Only whisper-1 is currently available.
 	pub model: String,
 	/// An optional text to guide the model's style or continue a previous audio segment.
 	/// The prompt should match the audio language.
 	pub prompt: Option<String>,
-	/// The format of the transcript output, in one of these options: json, text, srt, verbose_json, or vtt.
-	pub response_format: Option<String>,
+	/// The format of the transcript output, which decides the variant of the returned [`Transcription`].
+	/// Defaults to json
+	pub response_format: Option<TranscriptionFormat>,
 	/// The sampling temperature, between 0 and 1.
 	/// Higher values like 0.8 will make the output more random,
 	/// while lower values like 0.2 will make it more focused and deterministic. If set to 0,
@@ -32,112 +46,1091 @@ pub struct AudioBody {
 	/// The language of the input audio. Supplying the input language in ISO-639-1 format will improve accuracy and latency.
 	/// ISO-639-1: https://en.wikipedia.org/wiki/List_of_ISO_639-1_codes
 	pub language: Option<String>,
+	/// The timestamp granularities to populate for this transcription.
+	/// Requires the `verbose_json` response format, and is not supported for translations.
+	/// Defaults to segment
+	pub timestamp_granularities: Option<Vec<TimestampGranularity>>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Audio {
-	pub text: Option<String>,
+impl AudioBody {
+	/// Checks the audio format and size before uploading it, see [`AudioInput::validate`].
+	pub fn validate(&mut self) -> ApiResult<()> {
+		self.file.validate()
+	}
 }
 
-pub trait AudioApi {
-	/// Transcribes audio into the input language.
-	fn audio_transcription_create(&self, audio_body: AudioBody) -> ApiResult<Audio>;
-	/// Translates audio into into English.
-	fn audio_translation_create(&self, audio_body: AudioBody) -> ApiResult<Audio>;
+/// The formats accepted by the transcriptions and translations endpoints.
+const AUDIO_EXTENSIONS: [&str; 9] =
+	["flac", "m4a", "mp3", "mp4", "mpeg", "mpga", "ogg", "wav", "webm"];
+/// The size limit of the transcriptions and translations endpoints.
+const MAX_AUDIO_BYTES: u64 = 25 * 1024 * 1024;
+
+/// An audio file to upload, whose filename and MIME type tell the API its format.
+pub struct AudioInput {
+	reader: Box<dyn Read + Send>,
+	filename: String,
+	mime: Mime,
+	/// The size of the audio, if known without reading it.
+	len: Option<u64>,
 }
 
-impl AudioApi for OpenAI {
-	fn audio_transcription_create(&self, audio_body: AudioBody) -> ApiResult<Audio> {
-		let mut send_data = Multipart::new();
+impl AudioInput {
+	/// The audio file at `path`, its MIME type derived from the extension.
+	pub fn from_path<P: AsRef<Path>>(path: P) -> ApiResult<AudioInput> {
+		let path = path.as_ref();
+		let filename = match path.file_name() {
+			Some(name) => name.to_string_lossy().into_owned(),
+			None => return Err(Error::InvalidInput(format!("Not a file: {}", path.display()))),
+		};
+		Ok(AudioInput::from_file(File::open(path)?, &filename))
+	}
+
+	/// An opened audio file, `filename` being e.g. `recording.wav`.
+	pub fn from_file(file: File, filename: &str) -> AudioInput {
+		let len = file.metadata().ok().filter(|m| m.is_file()).map(|m| m.len());
+		AudioInput { len, ..AudioInput::from_reader(file, filename) }
+	}
+
+	/// An in-memory recording, `filename` being e.g. `recording.webm`.
+	pub fn from_bytes(bytes: Vec<u8>, filename: &str) -> AudioInput {
+		let len = Some(bytes.len() as u64);
+		AudioInput { len, ..AudioInput::from_reader(Cursor::new(bytes), filename) }
+	}
+
+	/// Audio read from `reader`, `filename` being e.g. `recording.ogg`.
+	pub fn from_reader<R: Read + Send + 'static>(reader: R, filename: &str) -> AudioInput {
+		AudioInput {
+			reader: Box::new(reader),
+			filename: filename.to_string(),
+			mime: audio_mime(filename),
+			len: None,
+		}
+	}
+
+	/// Overrides the MIME type derived from the filename.
+	pub fn with_mime(self, mime: Mime) -> AudioInput {
+		AudioInput { mime, ..self }
+	}
+
+	pub fn filename(&self) -> &str {
+		&self.filename
+	}
+
+	pub fn mime(&self) -> &Mime {
+		&self.mime
+	}
+
+	/// Checks the filename has one of the extensions accepted by the API and the audio is at most 25 MB,
+	/// returning [`Error::InvalidInput`] otherwise, see [`ChunkedTranscriptionBody`] for longer recordings.
+	///
+	/// Files and bytes are measured without reading them, but audio from
+	/// [`AudioInput::from_reader`] is read into memory, up to 25 MB, to measure it.
+	pub fn validate(&mut self) -> ApiResult<()> {
+		let extension = Path::new(&self.filename).extension().and_then(|e| e.to_str());
+		let extension = extension.unwrap_or_default().to_lowercase();
+		if !AUDIO_EXTENSIONS.contains(&extension.as_str()) {
+			return Err(Error::InvalidInput(format!(
+				"{}: unsupported audio format, expected one of {}",
+				self.filename,
+				AUDIO_EXTENSIONS.join(", ")
+			)));
+		}
+		let len = match self.len {
+			Some(len) => len,
+			None => {
+				let mut bytes = Vec::new();
+				(&mut self.reader).take(MAX_AUDIO_BYTES + 1).read_to_end(&mut bytes)?;
+				let len = bytes.len() as u64;
+				// The bytes read are put back in front of the rest of the audio.
+				let rest = std::mem::replace(&mut self.reader, Box::new(io::empty()));
+				self.reader = Box::new(Cursor::new(bytes).chain(rest));
+				len
+			},
+		};
+		if len > MAX_AUDIO_BYTES {
+			return Err(Error::InvalidInput(format!(
+				"{}: larger than the 25 MB limit",
+				self.filename
+			)));
+		}
+		self.len = Some(len);
+		Ok(())
+	}
+}
+
+impl fmt::Debug for AudioInput {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("AudioInput")
+			.field("filename", &self.filename)
+			.field("mime", &self.mime)
+			.finish_non_exhaustive()
+	}
+}
+
+/// The MIME type of an audio file, from the extension of its name.
+fn audio_mime(filename: &str) -> Mime {
+	let extension = Path::new(filename).extension().and_then(|e| e.to_str()).unwrap_or_default();
+	let mime = match extension.to_lowercase().as_str() {
+		"flac" => "audio/flac",
+		"m4a" | "mp4" => "audio/mp4",
+		"mp3" | "mpeg" | "mpga" => "audio/mpeg",
+		"oga" | "ogg" => "audio/ogg",
+		"wav" => "audio/wav",
+		"webm" => "audio/webm",
+		_ => return mime::APPLICATION_OCTET_STREAM,
+	};
+	mime.parse().unwrap()
+}
 
-		send_data.add_text("model", audio_body.model);
-		if let Some(prompt) = audio_body.prompt {
-			send_data.add_text("prompt", prompt);
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TranscriptionFormat {
+	Json,
+	Text,
+	Srt,
+	VerboseJson,
+	Vtt,
+}
+
+impl TranscriptionFormat {
+	fn as_str(self) -> &'static str {
+		match self {
+			Self::Json => "json",
+			Self::Text => "text",
+			Self::Srt => "srt",
+			Self::VerboseJson => "verbose_json",
+			Self::Vtt => "vtt",
 		}
-		if let Some(response_format) = audio_body.response_format {
-			send_data.add_text("response_format", response_format);
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimestampGranularity {
+	Word,
+	Segment,
+}
+
+/// A transcript, in the variant matching the requested [`TranscriptionFormat`].
+#[derive(Debug, Clone)]
+pub enum Transcription {
+	/// The transcribed text, for the `json` and `text` formats.
+	Text(String),
+	/// The SubRip subtitles, for the `srt` format.
+	Srt(String),
+	/// The WebVTT subtitles, for the `vtt` format.
+	Vtt(String),
+	/// The transcribed text with timestamps, for the `verbose_json` format.
+	Verbose(VerboseTranscription),
+}
+
+impl Transcription {
+	/// The transcribed text, or the raw subtitles for the `srt` and `vtt` formats.
+	pub fn text(&self) -> &str {
+		match self {
+			Self::Text(text) | Self::Srt(text) | Self::Vtt(text) => text,
+			Self::Verbose(verbose) => &verbose.text,
 		}
-		if let Some(temperature) = audio_body.temperature {
-			send_data.add_text("temperature", temperature.to_string());
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VerboseTranscription {
+	/// transcribe or translate.
+	pub task: Option<String>,
+	/// The language of the input audio.
+	pub language: String,
+	/// The duration of the input audio, in seconds.
+	pub duration: f64,
+	pub text: String,
+	/// The segments of the transcribed text, when requested by `timestamp_granularities`.
+	pub segments: Option<Vec<TranscriptionSegment>>,
+	/// The words of the transcribed text, when requested by `timestamp_granularities`.
+	pub words: Option<Vec<TranscriptionWord>>,
+}
+
+impl VerboseTranscription {
+	/// The segments as SubRip subtitles.
+	pub fn to_srt(&self) -> String {
+		let mut srt = String::new();
+		for (i, segment) in self.segments.iter().flatten().enumerate() {
+			srt += &format!(
+				"{}\n{} --> {}\n{}\n\n",
+				i + 1,
+				subtitle_time(segment.start, ','),
+				subtitle_time(segment.end, ','),
+				segment.text.trim()
+			);
 		}
-		if let Some(language) = audio_body.language {
-			send_data.add_text("language", language);
+		srt
+	}
+
+	/// The segments as WebVTT subtitles.
+	pub fn to_vtt(&self) -> String {
+		let mut vtt = "WEBVTT\n\n".to_string();
+		for segment in self.segments.iter().flatten() {
+			vtt += &format!(
+				"{} --> {}\n{}\n\n",
+				subtitle_time(segment.start, '.'),
+				subtitle_time(segment.end, '.'),
+				segment.text.trim()
+			);
 		}
+		vtt
+	}
+}
 
-		send_data.add_stream("file", audio_body.file, Some("audio.mp3"), None);
+/// Formats seconds as `HH:MM:SS,mmm`, with the given separator before the milliseconds.
+fn subtitle_time(seconds: f64, separator: char) -> String {
+	let millis = (seconds.max(0.0) * 1000.0).round() as u64;
+	let (hours, minutes) = (millis / 3_600_000, millis / 60_000 % 60);
+	format!("{hours:02}:{minutes:02}:{:02}{separator}{:03}", millis / 1000 % 60, millis % 1000)
+}
 
-		let res = self.post_multipart(AUDIO_TRANSCRIPTION_CREATE, send_data)?;
-		let audio: Audio = serde_json::from_value(res.clone()).unwrap();
-		Ok(audio)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TranscriptionSegment {
+	pub id: u32,
+	/// Seek offset of the segment.
+	pub seek: u32,
+	/// Start time of the segment, in seconds.
+	pub start: f64,
+	/// End time of the segment, in seconds.
+	pub end: f64,
+	pub text: String,
+	#[serde(default)]
+	pub tokens: Vec<u32>,
+	pub temperature: f64,
+	/// Average logprob of the segment. If the value is lower than -1,
+	/// consider the logprobs failed.
+	pub avg_logprob: f64,
+	/// Compression ratio of the segment. If the value is greater than 2.4,
+	/// consider the compression failed.
+	pub compression_ratio: f64,
+	/// Probability of no speech in the segment. If the value is higher than 1.0
+	/// and the `avg_logprob` is below -1, consider this segment silent.
+	pub no_speech_prob: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TranscriptionWord {
+	pub word: String,
+	/// Start time of the word, in seconds.
+	pub start: f64,
+	/// End time of the word, in seconds.
+	pub end: f64,
+}
+
+/// The layout of integer PCM samples, interleaved and little-endian.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PcmFormat {
+	pub sample_rate: u32,
+	pub channels: u16,
+	/// 8, 16, 24 or 32.
+	pub bits_per_sample: u16,
+}
+
+impl PcmFormat {
+	/// The size of a frame, one sample per channel, in bytes.
+	pub fn block_align(&self) -> usize {
+		self.channels as usize * self.bits_per_sample.div_ceil(8) as usize
+	}
+}
+
+/// A recording as raw PCM samples, which can be split without decoding.
+#[derive(Clone)]
+pub struct PcmAudio {
+	format: PcmFormat,
+	data: Vec<u8>,
+}
+
+impl PcmAudio {
+	/// Reads the samples of an integer PCM WAV file.
+	pub fn from_wav(wav: &[u8]) -> ApiResult<PcmAudio> {
+		let (format, data) = crate::wav::parse(wav)
+			.map_err(|e| Error::RequestError(format!("Invalid WAV file: {e}")))?;
+		PcmAudio::from_pcm(data.to_vec(), format)
+	}
+
+	pub fn from_wav_file<P: AsRef<Path>>(path: P) -> ApiResult<PcmAudio> {
+		PcmAudio::from_wav(&std::fs::read(path)?)
+	}
+
+	/// Raw samples, e.g. the `pcm` output of [`AudioApi::audio_speech_create`],
+	/// which is 24kHz, 16-bit and mono. A trailing partial frame is dropped.
+	pub fn from_pcm(mut data: Vec<u8>, format: PcmFormat) -> ApiResult<PcmAudio> {
+		if format.sample_rate == 0 || format.block_align() == 0 {
+			return Err(Error::RequestError(format!("Invalid PCM format: {format:?}")));
+		}
+		data.truncate(data.len() - data.len() % format.block_align());
+		Ok(PcmAudio { format, data })
+	}
+
+	pub fn format(&self) -> PcmFormat {
+		self.format
+	}
+
+	pub fn duration(&self) -> Duration {
+		Duration::from_secs_f64(self.frames() as f64 / self.format.sample_rate as f64)
+	}
+
+	fn frames(&self) -> usize {
+		self.data.len() / self.format.block_align()
+	}
+
+	/// The frames of `range` as a WAV file.
+	fn wav(&self, range: Range<usize>) -> Vec<u8> {
+		let block_align = self.format.block_align();
+		crate::wav::write(
+			&self.format,
+			&self.data[range.start * block_align..range.end * block_align],
+		)
+	}
+}
+
+impl fmt::Debug for PcmAudio {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("PcmAudio")
+			.field("format", &self.format)
+			.field("duration", &self.duration())
+			.finish()
 	}
+}
 
-	fn audio_translation_create(&self, audio_body: AudioBody) -> ApiResult<Audio> {
-		let mut send_data = Multipart::new();
+/// A long recording to transcribe in chunks, see [`AudioApi::audio_transcription_chunked`].
+#[derive(Debug, Clone)]
+pub struct ChunkedTranscriptionBody {
+	pub audio: PcmAudio,
+	/// ID of the model to use, which must support the `verbose_json` format, e.g. whisper-1.
+	pub model: String,
+	/// An optional text to guide the model's style for the first chunk.
+	/// The following chunks are prompted with the end of the previous transcript.
+	pub prompt: Option<String>,
+	/// The sampling temperature, between 0 and 1.
+	pub temperature: Option<f32>,
+	/// The language of the input audio, in ISO-639-1 format.
+	pub language: Option<String>,
+	/// The maximum size of the uploaded chunks, in bytes.
+	/// Defaults to 24 MiB, below the 25 MB limit of the API
+	pub max_chunk_bytes: Option<usize>,
+	/// The duration shared by consecutive chunks, so that the words cut at the end
+	/// of a chunk are transcribed whole in the next one.
+	/// Defaults to 2 seconds
+	pub overlap: Option<Duration>,
+}
 
-		send_data.add_text("model", audio_body.model);
-		if let Some(prompt) = audio_body.prompt {
-			send_data.add_text("prompt", prompt);
+impl ChunkedTranscriptionBody {
+	pub fn new(audio: PcmAudio, model: &str) -> ChunkedTranscriptionBody {
+		ChunkedTranscriptionBody {
+			audio,
+			model: model.to_string(),
+			prompt: None,
+			temperature: None,
+			language: None,
+			max_chunk_bytes: None,
+			overlap: None,
 		}
-		if let Some(response_format) = audio_body.response_format {
-			send_data.add_text("response_format", response_format);
+	}
+}
+
+const DEFAULT_MAX_CHUNK_BYTES: usize = 24 * 1024 * 1024;
+const DEFAULT_OVERLAP: Duration = Duration::from_secs(2);
+/// The number of characters of the previous transcript given as prompt to the next chunk,
+/// whisper only considering the last 224 tokens of a prompt.
+const PROMPT_TAIL_CHARS: usize = 200;
+
+/// How a recording is split into overlapping chunks, and stitched back.
+struct ChunkPlan {
+	/// The frames of each chunk.
+	ranges: Vec<Range<usize>>,
+	overlap_frames: usize,
+}
+
+impl ChunkPlan {
+	fn new(body: &ChunkedTranscriptionBody) -> ApiResult<ChunkPlan> {
+		let format = body.audio.format;
+		let max_bytes = body.max_chunk_bytes.unwrap_or(DEFAULT_MAX_CHUNK_BYTES);
+		let chunk_frames = max_bytes.saturating_sub(crate::wav::HEADER_LEN) / format.block_align();
+		let overlap = body.overlap.unwrap_or(DEFAULT_OVERLAP).as_secs_f64();
+		let overlap_frames = (overlap * format.sample_rate as f64) as usize;
+		if chunk_frames <= overlap_frames {
+			return Err(Error::RequestError(format!(
+				"Chunks of {max_bytes} bytes are shorter than their overlap of {overlap}s"
+			)));
 		}
-		if let Some(temperature) = audio_body.temperature {
-			send_data.add_text("temperature", temperature.to_string());
+		let total = body.audio.frames();
+		let mut ranges = Vec::new();
+		let mut start = 0;
+		loop {
+			let end = (start + chunk_frames).min(total);
+			ranges.push(start..end);
+			if end == total {
+				break;
+			}
+			start = end - overlap_frames;
 		}
-		if let Some(language) = audio_body.language {
-			send_data.add_text("language", language);
+		Ok(ChunkPlan { ranges, overlap_frames })
+	}
+
+	/// The request transcribing the chunk `index`.
+	fn audio_body(
+		&self,
+		body: &ChunkedTranscriptionBody,
+		index: usize,
+		previous: Option<&VerboseTranscription>,
+	) -> AudioBody {
+		let prompt = match previous {
+			Some(previous) => {
+				let text = previous.text.trim();
+				let start =
+					text.char_indices().rev().nth(PROMPT_TAIL_CHARS - 1).map_or(0, |(i, _)| i);
+				Some(text[start..].to_string())
+			},
+			None => body.prompt.clone(),
+		};
+		let wav = body.audio.wav(self.ranges[index].clone());
+		AudioBody {
+			file: AudioInput::from_bytes(wav, &format!("chunk-{index}.wav")),
+			model: body.model.clone(),
+			prompt,
+			response_format: Some(TranscriptionFormat::VerboseJson),
+			temperature: body.temperature,
+			language: body.language.clone(),
+			timestamp_granularities: Some(vec![TimestampGranularity::Segment]),
 		}
+	}
 
-		send_data.add_stream("file", audio_body.file, Some("audio.mp3"), None);
+	/// Shifts the segments of the chunks to the start of the recording, and removes the
+	/// duplicates of the overlaps: a segment starting in the first half of an overlap is kept
+	/// from the earlier chunk, one starting in the second half from the later chunk.
+	fn stitch(&self, audio: &PcmAudio, chunks: Vec<VerboseTranscription>) -> VerboseTranscription {
+		let sample_rate = audio.format.sample_rate as f64;
+		let cut = |index: usize| match index {
+			0 => f64::NEG_INFINITY,
+			i if i == self.ranges.len() => f64::INFINITY,
+			i => (self.ranges[i].start + self.overlap_frames / 2) as f64 / sample_rate,
+		};
+		let mut language = None;
+		let mut segments = Vec::new();
+		for (index, chunk) in chunks.into_iter().enumerate() {
+			let offset = self.ranges[index].start as f64 / sample_rate;
+			let kept = cut(index)..cut(index + 1);
+			language = language.or(Some(chunk.language));
+			for mut segment in chunk.segments.unwrap_or_default() {
+				segment.start += offset;
+				segment.end += offset;
+				if kept.contains(&segment.start) {
+					segment.id = segments.len() as u32;
+					segments.push(segment);
+				}
+			}
+		}
+		VerboseTranscription {
+			task: Some("transcribe".to_string()),
+			language: language.unwrap_or_default(),
+			duration: audio.duration().as_secs_f64(),
+			text: segments.iter().map(|s| s.text.as_str()).collect::<String>().trim().to_string(),
+			segments: Some(segments),
+			words: None,
+		}
+	}
+}
 
-		let res = self.post_multipart(AUDIO_TRANSLATIONS_CREATE, send_data)?;
-		let audio: Audio = serde_json::from_value(res.clone()).unwrap();
+fn verbose(transcription: Transcription) -> ApiResult<VerboseTranscription> {
+	match transcription {
+		Transcription::Verbose(verbose) => Ok(verbose),
+		other => Err(Error::decode("Not a verbose transcription", other.text())),
+	}
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SpeechBody {
+	/// One of the available TTS models: tts-1, tts-1-hd or gpt-4o-mini-tts.
+	pub model: String,
+	/// The text to generate audio for. The maximum length is 4096 characters.
+	pub input: String,
+	/// The voice to use when generating the audio, e.g. alloy, ash, coral, echo, fable,
+	/// onyx, nova, sage or shimmer.
+	pub voice: String,
+	/// The format of the generated audio.
+	/// Defaults to mp3
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub response_format: Option<SpeechFormat>,
+	/// The speed of the generated audio, from 0.25 to 4.0.
+	/// Defaults to 1
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub speed: Option<f32>,
+	/// Control the voice with additional instructions, e.g. the tone.
+	/// Does not work with tts-1 or tts-1-hd.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub instructions: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SpeechFormat {
+	Mp3,
+	Opus,
+	Aac,
+	Flac,
+	Wav,
+	/// Raw samples in 24kHz, 16-bit signed, little-endian, without header.
+	Pcm,
+}
+
+pub trait AudioApi {
+	/// Transcribes audio into the input language.
+	fn audio_transcription_create(&self, audio_body: AudioBody) -> ApiResult<Transcription>;
+	/// Translates audio into into English.
+	fn audio_translation_create(&self, audio_body: AudioBody) -> ApiResult<Transcription>;
+	/// Generates audio from the input text, writing it to `writer` as it is received.
+	/// Returns the number of bytes written.
+	fn audio_speech_create(
+		&self,
+		speech_body: &SpeechBody,
+		writer: &mut dyn Write,
+	) -> ApiResult<u64>;
+	/// Generates audio from the input text.
+	fn audio_speech_bytes(&self, speech_body: &SpeechBody) -> ApiResult<Vec<u8>>;
+	/// Transcribes a recording of any length, in overlapping chunks below the upload limit.
+	///
+	/// Each chunk is prompted with the end of the previous transcript, and the segments
+	/// are stitched back with timestamps from the start of the recording,
+	/// see [`VerboseTranscription::to_srt`] and [`VerboseTranscription::to_vtt`].
+	fn audio_transcription_chunked(
+		&self,
+		body: &ChunkedTranscriptionBody,
+	) -> ApiResult<VerboseTranscription>;
+}
+
+impl AudioApi for OpenAI {
+	fn audio_transcription_create(&self, audio_body: AudioBody) -> ApiResult<Transcription> {
+		let format = audio_body.response_format;
+		let send_data = build_multipart(audio_body);
+		let mut body = String::new();
+		self.post_multipart_raw(AUDIO_TRANSCRIPTION_CREATE, send_data)?
+			.read_to_string(&mut body)?;
+		parse_transcription(format, body)
+	}
+
+	fn audio_translation_create(&self, audio_body: AudioBody) -> ApiResult<Transcription> {
+		let format = audio_body.response_format;
+		let send_data = build_multipart(audio_body);
+		let mut body = String::new();
+		self.post_multipart_raw(AUDIO_TRANSLATIONS_CREATE, send_data)?.read_to_string(&mut body)?;
+		parse_transcription(format, body)
+	}
+
+	fn audio_speech_create(
+		&self,
+		speech_body: &SpeechBody,
+		writer: &mut dyn Write,
+	) -> ApiResult<u64> {
+		let request_body = to_json(speech_body)?;
+		let mut reader = self.post_raw(AUDIO_SPEECH_CREATE, request_body)?;
+		Ok(io::copy(&mut reader, writer)?)
+	}
+
+	fn audio_speech_bytes(&self, speech_body: &SpeechBody) -> ApiResult<Vec<u8>> {
+		let mut audio = Vec::new();
+		self.audio_speech_create(speech_body, &mut audio)?;
+		Ok(audio)
+	}
+
+	fn audio_transcription_chunked(
+		&self,
+		body: &ChunkedTranscriptionBody,
+	) -> ApiResult<VerboseTranscription> {
+		let plan = ChunkPlan::new(body)?;
+		let mut chunks = Vec::new();
+		for index in 0..plan.ranges.len() {
+			let audio_body = plan.audio_body(body, index, chunks.last());
+			chunks.push(verbose(self.audio_transcription_create(audio_body)?)?);
+		}
+		Ok(plan.stitch(&body.audio, chunks))
+	}
+}
+
+fn build_multipart(audio_body: AudioBody) -> Multipart<'static, 'static> {
+	let mut send_data = Multipart::new();
+
+	send_data.add_text("model", audio_body.model);
+	if let Some(prompt) = audio_body.prompt {
+		send_data.add_text("prompt", prompt);
+	}
+	if let Some(response_format) = audio_body.response_format {
+		send_data.add_text("response_format", response_format.as_str());
+	}
+	if let Some(temperature) = audio_body.temperature {
+		send_data.add_text("temperature", temperature.to_string());
+	}
+	if let Some(language) = audio_body.language {
+		send_data.add_text("language", language);
+	}
+	for granularity in audio_body.timestamp_granularities.unwrap_or_default() {
+		let granularity = match granularity {
+			TimestampGranularity::Word => "word",
+			TimestampGranularity::Segment => "segment",
+		};
+		send_data.add_text("timestamp_granularities[]", granularity);
+	}
+
+	let AudioInput { reader, filename, mime, .. } = audio_body.file;
+	send_data.add_stream("file", reader, Some(filename), Some(mime));
+	send_data
+}
+
+/// Parses a transcription response body of the given format.
+fn parse_transcription(
+	format: Option<TranscriptionFormat>,
+	body: String,
+) -> ApiResult<Transcription> {
+	match format.unwrap_or(TranscriptionFormat::Json) {
+		TranscriptionFormat::Json => {
+			let res = serde_json::from_str::<Json>(&body).map_err(|e| Error::decode(e, &body))?;
+			match res.get("text").and_then(Json::as_str) {
+				Some(text) => Ok(Transcription::Text(text.to_string())),
+				None => Err(Error::decode("No text", &body)),
+			}
+		},
+		TranscriptionFormat::Text => Ok(Transcription::Text(body)),
+		TranscriptionFormat::Srt => Ok(Transcription::Srt(body)),
+		TranscriptionFormat::Vtt => Ok(Transcription::Vtt(body)),
+		TranscriptionFormat::VerboseJson => {
+			let verbose = serde_json::from_str(&body).map_err(|e| Error::decode(e, &body))?;
+			Ok(Transcription::Verbose(verbose))
+		},
+	}
+}
+
+/// The async counterpart of [`AudioApi`].
+#[cfg(feature = "async")]
+pub trait AsyncAudioApi {
+	/// Transcribes audio into the input language.
+	fn audio_transcription_create(
+		&self,
+		audio_body: AudioBody,
+	) -> impl Future<Output = ApiResult<Transcription>> + Send;
+	/// Translates audio into into English.
+	fn audio_translation_create(
+		&self,
+		audio_body: AudioBody,
+	) -> impl Future<Output = ApiResult<Transcription>> + Send;
+	/// Generates audio from the input text, writing it to `writer` as it is received.
+	/// Returns the number of bytes written.
+	fn audio_speech_create(
+		&self,
+		speech_body: &SpeechBody,
+		writer: &mut (dyn Write + Send),
+	) -> impl Future<Output = ApiResult<u64>> + Send;
+	/// Generates audio from the input text.
+	fn audio_speech_bytes(
+		&self,
+		speech_body: &SpeechBody,
+	) -> impl Future<Output = ApiResult<Vec<u8>>> + Send;
+	/// Transcribes a recording of any length, in overlapping chunks below the upload limit.
+	fn audio_transcription_chunked(
+		&self,
+		body: &ChunkedTranscriptionBody,
+	) -> impl Future<Output = ApiResult<VerboseTranscription>> + Send;
+}
+
+#[cfg(feature = "async")]
+impl AsyncAudioApi for AsyncOpenAI {
+	async fn audio_transcription_create(&self, audio_body: AudioBody) -> ApiResult<Transcription> {
+		let format = audio_body.response_format;
+		let form_data = FormData::new(build_multipart(audio_body))?;
+		let mut res = self.post_multipart_raw(AUDIO_TRANSCRIPTION_CREATE, form_data).await?;
+		let body = read_text(res.as_mut()).await?;
+		parse_transcription(format, body)
+	}
+
+	async fn audio_translation_create(&self, audio_body: AudioBody) -> ApiResult<Transcription> {
+		let format = audio_body.response_format;
+		let form_data = FormData::new(build_multipart(audio_body))?;
+		let mut res = self.post_multipart_raw(AUDIO_TRANSLATIONS_CREATE, form_data).await?;
+		let body = read_text(res.as_mut()).await?;
+		parse_transcription(format, body)
+	}
+
+	async fn audio_speech_create(
+		&self,
+		speech_body: &SpeechBody,
+		writer: &mut (dyn Write + Send),
+	) -> ApiResult<u64> {
+		let request_body = to_json(speech_body)?;
+		let mut response = self.post_raw(AUDIO_SPEECH_CREATE, request_body).await?;
+		let mut written = 0;
+		while let Some(chunk) = response.chunk().await? {
+			writer.write_all(&chunk)?;
+			written += chunk.len() as u64;
+		}
+		Ok(written)
+	}
+
+	async fn audio_speech_bytes(&self, speech_body: &SpeechBody) -> ApiResult<Vec<u8>> {
+		let mut audio = Vec::new();
+		self.audio_speech_create(speech_body, &mut audio).await?;
 		Ok(audio)
 	}
+
+	async fn audio_transcription_chunked(
+		&self,
+		body: &ChunkedTranscriptionBody,
+	) -> ApiResult<VerboseTranscription> {
+		let plan = ChunkPlan::new(body)?;
+		let mut chunks = Vec::new();
+		for index in 0..plan.ranges.len() {
+			let audio_body = plan.audio_body(body, index, chunks.last());
+			chunks.push(verbose(self.audio_transcription_create(audio_body).await?)?);
+		}
+		Ok(plan.stitch(&body.audio, chunks))
+	}
 }
 
 #[cfg(test)]
 mod tests {
-	use std::fs::File;
+	use std::io::{self, Read};
+	use std::time::Duration;
+
+	use serde_json::json;
 
 	use crate::{
-		apis::audio::{AudioApi, AudioBody},
-		openai::new_test_openai,
+		apis::audio::{
+			AudioApi, AudioBody, AudioInput, ChunkedTranscriptionBody, PcmAudio, PcmFormat,
+			SpeechBody, SpeechFormat, TimestampGranularity, Transcription, TranscriptionFormat,
+			VerboseTranscription,
+		},
+		openai::new_mock_openai,
+		Error, MockResponse,
 	};
 
 	#[test]
 	fn test_audio_transcription() {
-		let openai = new_test_openai();
-		let file = File::open("test_files/audio.mp3").unwrap();
+		let (openai, transport) = new_mock_openai();
+		transport.push(MockResponse::json(200, json!({ "text": "千里之行，始于足下。" })));
 		let multipart = AudioBody {
-			file,
+			file: AudioInput::from_path("test_files/audio.mp3").unwrap(),
 			model: "whisper-1".to_string(),
 			prompt: None,
 			response_format: None,
 			temperature: None,
 			language: Some("zh".to_string()),
+			timestamp_granularities: None,
 		};
 		let rs = openai.audio_transcription_create(multipart);
-		let audio = rs.unwrap();
-		let text = audio.text.unwrap();
-		assert!(text.contains("千里"));
+		let transcription = rs.unwrap();
+		assert!(transcription.text().contains("千里"));
+
+		let request = &transport.requests()[0];
+		assert_eq!(request.url, "https://api.openai.com/v1/audio/transcriptions");
+		let form = String::from_utf8_lossy(request.body.as_ref().unwrap()).into_owned();
+		assert!(form.contains("name=\"model\"\r\n\r\nwhisper-1\r\n"));
+		assert!(form.contains("name=\"language\"\r\n\r\nzh\r\n"));
+		assert!(form.contains("name=\"file\"; filename=\"audio.mp3\"\r\nContent-Type: audio/mpeg"));
 	}
 
 	#[test]
 	fn test_audio_translation() {
-		let openai = new_test_openai();
-		let file = File::open("test_files/audio.mp3").unwrap();
+		let (openai, transport) = new_mock_openai();
+		transport.push(MockResponse::json(
+			200,
+			json!({ "text": "A journey of thousands of miles begins with a single step." }),
+		));
 		let multipart = AudioBody {
-			file,
+			file: AudioInput::from_path("test_files/audio.mp3").unwrap(),
 			model: "whisper-1".to_string(),
 			prompt: None,
 			response_format: None,
 			temperature: None,
 			language: None,
+			timestamp_granularities: None,
 		};
 		let rs = openai.audio_translation_create(multipart);
-		let audio = rs.unwrap();
-		let text = audio.text.unwrap();
-		assert!(text.contains("thousands of miles"));
+		let transcription = rs.unwrap();
+		assert!(transcription.text().contains("thousands of miles"));
+		assert_eq!(transport.requests()[0].url, "https://api.openai.com/v1/audio/translations");
+	}
+
+	fn mock_audio_body(format: TranscriptionFormat) -> AudioBody {
+		AudioBody {
+			file: AudioInput::from_path("test_files/audio.mp3").unwrap(),
+			model: "whisper-1".to_string(),
+			prompt: None,
+			response_format: Some(format),
+			temperature: None,
+			language: None,
+			timestamp_granularities: None,
+		}
+	}
+
+	#[test]
+	fn test_audio_transcription_formats() {
+		let (openai, transport) = new_mock_openai();
+		let srt = "1\n00:00:00,000 --> 00:00:02,000\nHello\n\n";
+		transport
+			.push(MockResponse::new(200, srt).header("content-type", "text/plain"))
+			.push(MockResponse::new(200, "Hello\n").header("content-type", "text/plain"))
+			.push(MockResponse::json(
+				200,
+				json!({
+					"task": "transcribe",
+					"language": "english",
+					"duration": 2.0,
+					"text": "Hello",
+					"words": [{ "word": "Hello", "start": 0.0, "end": 0.5 }],
+					"segments": [{
+						"id": 0,
+						"seek": 0,
+						"start": 0.0,
+						"end": 2.0,
+						"text": " Hello",
+						"tokens": [50364, 2425],
+						"temperature": 0.0,
+						"avg_logprob": -0.28,
+						"compression_ratio": 0.6,
+						"no_speech_prob": 0.01
+					}]
+				}),
+			));
+
+		match openai.audio_transcription_create(mock_audio_body(TranscriptionFormat::Srt)) {
+			Ok(Transcription::Srt(text)) => assert_eq!(text, srt),
+			other => panic!("unexpected result: {other:?}"),
+		}
+		let text = openai.audio_translation_create(mock_audio_body(TranscriptionFormat::Text));
+		assert_eq!(text.unwrap().text(), "Hello\n");
+		let body = AudioBody {
+			timestamp_granularities: Some(vec![
+				TimestampGranularity::Word,
+				TimestampGranularity::Segment,
+			]),
+			..mock_audio_body(TranscriptionFormat::VerboseJson)
+		};
+		match openai.audio_transcription_create(body) {
+			Ok(Transcription::Verbose(verbose)) => {
+				assert_eq!(verbose.language, "english");
+				assert_eq!(verbose.words.unwrap()[0].end, 0.5);
+				assert_eq!(verbose.segments.unwrap()[0].no_speech_prob, 0.01);
+			},
+			other => panic!("unexpected result: {other:?}"),
+		}
+
+		let requests = transport.requests();
+		let form = String::from_utf8_lossy(requests[2].body.as_ref().unwrap()).into_owned();
+		assert!(form.contains("name=\"response_format\"\r\n\r\nverbose_json\r\n"));
+		assert!(form.contains("name=\"timestamp_granularities[]\"\r\n\r\nword\r\n"));
+		assert!(form.contains("name=\"timestamp_granularities[]\"\r\n\r\nsegment\r\n"));
+	}
+
+	#[test]
+	fn test_audio_input() {
+		assert_eq!(AudioInput::from_bytes(vec![], "a.WAV").mime().as_ref(), "audio/wav");
+		assert_eq!(AudioInput::from_bytes(vec![], "a.m4a").mime().as_ref(), "audio/mp4");
+		assert_eq!(AudioInput::from_bytes(vec![], "a").mime(), &mime::APPLICATION_OCTET_STREAM);
+
+		let (openai, transport) = new_mock_openai();
+		transport.push(MockResponse::json(200, json!({ "text": "Hello" })));
+		let body = AudioBody {
+			file: AudioInput::from_bytes(b"RIFF....WAVE".to_vec(), "recording.wav"),
+			..mock_audio_body(TranscriptionFormat::Json)
+		};
+		assert_eq!(openai.audio_transcription_create(body).unwrap().text(), "Hello");
+		let request = &transport.requests()[0];
+		let form = String::from_utf8_lossy(request.body.as_ref().unwrap()).into_owned();
+		assert!(form.contains("filename=\"recording.wav\"\r\nContent-Type: audio/wav\r\n\r\nRIFF"));
+	}
+
+	#[test]
+	fn test_audio_input_validate() {
+		let mut input = AudioInput::from_bytes(b"RIFF....WAVE".to_vec(), "recording.WAV");
+		input.validate().unwrap();
+		let mut input = AudioInput::from_bytes(b"RIFF....WAVE".to_vec(), "recording.aiff");
+		assert!(matches!(input.validate(), Err(Error::InvalidInput(_))));
+		let mut input = AudioInput::from_bytes(vec![0; 25 * 1024 * 1024 + 1], "recording.wav");
+		let err = input.validate().unwrap_err();
+		assert_eq!(err.to_string(), "Invalid input: recording.wav: larger than the 25 MB limit");
+
+		// Files are measured from their metadata.
+		AudioInput::from_path("test_files/audio.mp3").unwrap().validate().unwrap();
+		let path = std::env::temp_dir().join(format!("large-{}.mp3", std::process::id()));
+		std::fs::File::create(&path).unwrap().set_len(25 * 1024 * 1024 + 1).unwrap();
+		let rs = AudioInput::from_path(&path).unwrap().validate();
+		std::fs::remove_file(&path).unwrap();
+		assert!(matches!(rs, Err(Error::InvalidInput(_))));
+
+		// Readers are buffered, and left whole when too large.
+		let mut input = AudioInput::from_reader(io::repeat(0).take(25 * 1024 * 1024 + 10), "a.wav");
+		assert!(matches!(input.validate(), Err(Error::InvalidInput(_))));
+		assert_eq!(io::copy(&mut input.reader, &mut io::sink()).unwrap(), 25 * 1024 * 1024 + 10);
+
+		// The validated audio is still uploaded.
+		let (openai, transport) = new_mock_openai();
+		transport.push(MockResponse::json(200, json!({ "text": "Hello" })));
+		let mut body = AudioBody {
+			file: AudioInput::from_bytes(b"RIFF....WAVE".to_vec(), "recording.wav"),
+			..mock_audio_body(TranscriptionFormat::Json)
+		};
+		body.validate().unwrap();
+		openai.audio_transcription_create(body).unwrap();
+		let form =
+			String::from_utf8_lossy(transport.requests()[0].body.as_ref().unwrap()).into_owned();
+		assert!(form.contains("RIFF....WAVE"));
+	}
+
+	fn verbose_response(segments: &[(f64, f64, &str)]) -> serde_json::Value {
+		let text: String = segments.iter().map(|s| s.2).collect();
+		let segments: Vec<_> = segments
+			.iter()
+			.enumerate()
+			.map(|(id, (start, end, text))| {
+				json!({
+					"id": id,
+					"seek": 0,
+					"start": start,
+					"end": end,
+					"text": text,
+					"tokens": [],
+					"temperature": 0.0,
+					"avg_logprob": -0.2,
+					"compression_ratio": 1.0,
+					"no_speech_prob": 0.01
+				})
+			})
+			.collect();
+		json!({ "language": "english", "duration": 4.0, "text": text, "segments": segments })
+	}
+
+	#[test]
+	fn test_audio_transcription_chunked() {
+		let (openai, transport) = new_mock_openai();
+		transport
+			.push(MockResponse::json(
+				200,
+				verbose_response(&[(0.0, 2.0, " Hello there."), (2.0, 4.0, " General Kenobi.")]),
+			))
+			.push(MockResponse::json(
+				200,
+				verbose_response(&[
+					(0.0, 1.0, " Kenobi."),
+					(1.0, 3.0, " You are"),
+					(3.0, 4.0, " a bold one."),
+				]),
+			))
+			.push(MockResponse::json(
+				200,
+				verbose_response(&[(0.0, 1.0, " one."), (1.0, 4.0, " Kill him.")]),
+			));
+
+		// 10 seconds of 8kHz 16-bit mono, in chunks of 4 seconds overlapping by 1 second.
+		let format = PcmFormat { sample_rate: 8000, channels: 1, bits_per_sample: 16 };
+		let audio = PcmAudio::from_pcm(vec![0; 160_000], format).unwrap();
+		let body = ChunkedTranscriptionBody {
+			max_chunk_bytes: Some(44 + 4 * 16_000),
+			overlap: Some(Duration::from_secs(1)),
+			prompt: Some("Star Wars".to_string()),
+			..ChunkedTranscriptionBody::new(audio, "whisper-1")
+		};
+		let transcription = openai.audio_transcription_chunked(&body).unwrap();
+		assert_eq!(
+			transcription.text,
+			"Hello there. General Kenobi. You are a bold one. Kill him."
+		);
+		assert_eq!(transcription.duration, 10.0);
+		let segments = transcription.segments.as_ref().unwrap();
+		let times: Vec<_> = segments.iter().map(|s| (s.id, s.start, s.end)).collect();
+		assert_eq!(
+			times,
+			[(0, 0.0, 2.0), (1, 2.0, 4.0), (2, 4.0, 6.0), (3, 6.0, 7.0), (4, 7.0, 10.0)]
+		);
+
+		let forms: Vec<_> = transport
+			.requests()
+			.iter()
+			.map(|r| String::from_utf8_lossy(r.body.as_ref().unwrap()).into_owned())
+			.collect();
+		assert_eq!(forms.len(), 3);
+		assert!(forms[0].contains("name=\"prompt\"\r\n\r\nStar Wars\r\n"));
+		assert!(forms[1].contains("name=\"prompt\"\r\n\r\nHello there. General Kenobi.\r\n"));
+		assert!(forms[2].contains("filename=\"chunk-2.wav\"\r\nContent-Type: audio/wav"));
+		// Each chunk is a WAV of 4 seconds, multipart headers aside.
+		assert!(forms[0].len() > 64_044 && forms[0].len() < 64_044 + 1024);
+	}
+
+	#[test]
+	fn test_subtitles() {
+		let transcription: VerboseTranscription = serde_json::from_value(verbose_response(&[
+			(0.0, 2.5, " Hello there."),
+			(3725.25, 3727.0, " General Kenobi."),
+		]))
+		.unwrap();
+		assert_eq!(
+			transcription.to_srt(),
+			"1\n00:00:00,000 --> 00:00:02,500\nHello there.\n\n\
+			 2\n01:02:05,250 --> 01:02:07,000\nGeneral Kenobi.\n\n"
+		);
+		assert_eq!(
+			transcription.to_vtt(),
+			"WEBVTT\n\n00:00:00.000 --> 00:00:02.500\nHello there.\n\n\
+			 01:02:05.250 --> 01:02:07.000\nGeneral Kenobi.\n\n"
+		);
+	}
+
+	#[test]
+	fn test_audio_speech() {
+		let (openai, transport) = new_mock_openai();
+		let audio = vec![0x49, 0x44, 0x33, 0x04, 0x00, 0xff, 0xfb];
+		transport.push(MockResponse::new(200, audio.clone()).header("content-type", "audio/mpeg"));
+		transport.push(MockResponse::json(
+			400,
+			json!({ "error": { "message": "Invalid voice", "type": "invalid_request_error" } }),
+		));
+		let body = SpeechBody {
+			model: "tts-1".to_string(),
+			input: "The quick brown fox jumped over the lazy dog.".to_string(),
+			voice: "alloy".to_string(),
+			response_format: Some(SpeechFormat::Mp3),
+			speed: Some(1.5),
+			..Default::default()
+		};
+		let mut out = Vec::new();
+		assert_eq!(openai.audio_speech_create(&body, &mut out).unwrap(), 7);
+		assert_eq!(out, audio);
+		assert!(openai.audio_speech_bytes(&body).is_err());
+
+		let request = &transport.requests()[0];
+		assert_eq!(request.url, "https://api.openai.com/v1/audio/speech");
+		assert_eq!(
+			request.json().unwrap(),
+			json!({
+				"model": "tts-1",
+				"input": "The quick brown fox jumped over the lazy dog.",
+				"voice": "alloy",
+				"response_format": "mp3",
+				"speed": 1.5
+			})
+		);
+	}
+
+	#[cfg(feature = "async")]
+	#[tokio::test]
+	async fn test_async_audio_transcription() {
+		use crate::{apis::audio::AsyncAudioApi, openai::new_mock_async_openai};
+
+		let (openai, transport) = new_mock_async_openai();
+		transport.push(MockResponse::json(200, json!({ "text": "千里之行，始于足下。" })));
+		let multipart = AudioBody {
+			file: AudioInput::from_path("test_files/audio.mp3").unwrap(),
+			model: "whisper-1".to_string(),
+			prompt: None,
+			response_format: None,
+			temperature: None,
+			language: Some("zh".to_string()),
+			timestamp_granularities: None,
+		};
+		let rs = openai.audio_transcription_create(multipart).await;
+		assert!(rs.unwrap().text().contains("千里"));
+		let form =
+			String::from_utf8_lossy(transport.requests()[0].body.as_ref().unwrap()).into_owned();
+		assert!(form.contains("name=\"language\"\r\n\r\nzh\r\n"));
 	}
 }
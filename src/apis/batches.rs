@@ -0,0 +1,491 @@
+// Create large batches of API requests for asynchronous processing.
+// The Batch API returns completions within 24 hours for a 50% discount.
+// See: https://platform.openai.com/docs/api-reference/batch
+
+//! Batch API
+
+use std::collections::{HashMap, HashSet};
+use std::marker::PhantomData;
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::json;
+
+use crate::requests::{from_json, to_json, Requests};
+use crate::*;
+
+use super::chat::ChatBody;
+use super::completions::{Completion, CompletionsBody};
+use super::embeddings::{Embeddings, EmbeddingsBody};
+use super::files::{FileUploadBody, FilesApi};
+use super::{HasId, ListParams, Page, BATCHES, BATCHES_RETRIEVE};
+
+#[cfg(feature = "async")]
+use {super::files::AsyncFilesApi, crate::async_requests::AsyncRequests, std::future::Future};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchBody {
+	/// The ID of an uploaded JSONL file with the purpose `batch`, see [`BatchInput`].
+	pub input_file_id: String,
+	/// The endpoint of the requests of the file, e.g. `/v1/chat/completions`.
+	pub endpoint: String,
+	/// The time frame within which the batch should be processed. Currently only `24h`.
+	pub completion_window: String,
+	/// Up to 16 key-value pairs attached to the batch.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub metadata: Option<HashMap<String, String>>,
+}
+
+impl BatchBody {
+	/// A batch of the requests of `input_file_id`, to be completed within 24 hours.
+	pub fn new<T: BatchRequest>(input_file_id: &str) -> BatchBody {
+		BatchBody {
+			input_file_id: input_file_id.to_string(),
+			endpoint: T::ENDPOINT.to_string(),
+			completion_window: "24h".to_string(),
+			metadata: None,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Batch {
+	pub id: String,
+	pub object: Option<String>,
+	pub endpoint: String,
+	/// The errors of the input file, when the batch failed validation.
+	pub errors: Option<Json>,
+	pub input_file_id: String,
+	pub completion_window: String,
+	pub status: BatchStatus,
+	/// The ID of the file with the responses of the successful requests.
+	pub output_file_id: Option<String>,
+	/// The ID of the file with the responses of the failed requests.
+	pub error_file_id: Option<String>,
+	/// The Unix timestamp (in seconds) for when the batch was created.
+	pub created_at: u64,
+	pub completed_at: Option<u64>,
+	pub expires_at: Option<u64>,
+	pub request_counts: Option<BatchRequestCounts>,
+	pub metadata: Option<HashMap<String, String>>,
+}
+
+impl HasId for Batch {
+	fn id(&self) -> &str {
+		&self.id
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchStatus {
+	Validating,
+	Failed,
+	InProgress,
+	Finalizing,
+	Completed,
+	Expired,
+	Cancelling,
+	Cancelled,
+	/// A status added to the API after this version.
+	#[serde(other)]
+	Unknown,
+}
+
+impl BatchStatus {
+	/// Whether the batch is over. Expired and cancelled batches may still have
+	/// the results of the requests completed before.
+	pub fn is_finished(self) -> bool {
+		matches!(self, Self::Failed | Self::Completed | Self::Expired | Self::Cancelled)
+	}
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct BatchRequestCounts {
+	pub total: u32,
+	pub completed: u32,
+	pub failed: u32,
+}
+
+/// A request body which can be sent in a batch.
+pub trait BatchRequest: Serialize {
+	/// The endpoint of the requests, e.g. `/v1/chat/completions`.
+	const ENDPOINT: &'static str;
+	/// The body of the responses.
+	type Response: DeserializeOwned;
+}
+
+impl BatchRequest for ChatBody {
+	const ENDPOINT: &'static str = "/v1/chat/completions";
+	type Response = Completion;
+}
+
+impl BatchRequest for CompletionsBody {
+	const ENDPOINT: &'static str = "/v1/completions";
+	type Response = Completion;
+}
+
+impl BatchRequest for EmbeddingsBody {
+	const ENDPOINT: &'static str = "/v1/embeddings";
+	type Response = Embeddings;
+}
+
+/// Builds the JSONL input file of a batch, one line per request.
+#[derive(Debug)]
+pub struct BatchInput<T> {
+	jsonl: Vec<u8>,
+	custom_ids: HashSet<String>,
+	request: PhantomData<T>,
+}
+
+impl<T: BatchRequest> BatchInput<T> {
+	pub fn new() -> BatchInput<T> {
+		BatchInput { jsonl: Vec::new(), custom_ids: HashSet::new(), request: PhantomData }
+	}
+
+	/// Builds the input file of the `(custom_id, body)` pairs.
+	pub fn from_requests<'a, S>(requests: impl IntoIterator<Item = (S, &'a T)>) -> ApiResult<Self>
+	where
+		S: AsRef<str>,
+		T: 'a,
+	{
+		let mut input = BatchInput::new();
+		for (custom_id, body) in requests {
+			input.add(custom_id.as_ref(), body)?;
+		}
+		Ok(input)
+	}
+
+	/// Adds a request, `custom_id` identifying its response in the output file.
+	///
+	/// Fails if `custom_id` was already added, as the API rejects such files.
+	pub fn add(&mut self, custom_id: &str, body: &T) -> ApiResult<&mut Self> {
+		if !self.custom_ids.insert(custom_id.to_string()) {
+			return Err(Error::RequestError(format!("Duplicate batch custom_id: {custom_id}")));
+		}
+		let line = json!({
+			"custom_id": custom_id,
+			"method": "POST",
+			"url": T::ENDPOINT,
+			"body": to_json(body)?,
+		});
+		serde_json::to_writer(&mut self.jsonl, &line)
+			.map_err(|e| Error::RequestError(format!("Invalid body: {e}")))?;
+		self.jsonl.push(b'\n');
+		Ok(self)
+	}
+
+	/// The number of requests.
+	pub fn len(&self) -> usize {
+		self.custom_ids.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.custom_ids.is_empty()
+	}
+
+	/// The content of the input file.
+	pub fn as_bytes(&self) -> &[u8] {
+		&self.jsonl
+	}
+
+	/// The input file, to be uploaded with [`FilesApi::files_upload`].
+	pub fn into_upload(self, filename: &str) -> FileUploadBody {
+		FileUploadBody::from_bytes(self.jsonl, filename, "batch")
+	}
+}
+
+impl<T: BatchRequest> Default for BatchInput<T> {
+	fn default() -> Self {
+		BatchInput::new()
+	}
+}
+
+/// A line of a batch output or error file.
+#[derive(Debug, Deserialize)]
+struct BatchOutputLine {
+	custom_id: String,
+	response: Option<BatchOutputResponse>,
+	error: Option<Json>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BatchOutputResponse {
+	status_code: u16,
+	request_id: Option<String>,
+	body: Json,
+}
+
+/// Parses the content of a batch output or error file into the responses keyed by `custom_id`.
+///
+/// Failed requests are mapped to [`Error::ApiError`], as if they had been sent one by one.
+pub fn parse_batch_output<T: BatchRequest>(
+	jsonl: &[u8],
+) -> ApiResult<HashMap<String, ApiResult<T::Response>>> {
+	let mut results = HashMap::new();
+	let text = String::from_utf8_lossy(jsonl);
+	for line in text.lines().filter(|line| !line.trim().is_empty()) {
+		let output: BatchOutputLine =
+			serde_json::from_str(line).map_err(|e| Error::decode(e, line))?;
+		let result = match (output.response, output.error) {
+			(Some(response), _) if response.status_code < 400 => from_json(&response.body),
+			(Some(response), _) => {
+				let headers = response
+					.request_id
+					.map(|id| HashMap::from([("x-request-id".to_string(), id)]))
+					.unwrap_or_default();
+				Err(ApiError::new(Some(response.status_code), headers, response.body).into())
+			},
+			(None, error) => {
+				let body = json!({ "error": error.unwrap_or_default() });
+				Err(ApiError::new(None, HashMap::new(), body).into())
+			},
+		};
+		results.insert(output.custom_id, result);
+	}
+	Ok(results)
+}
+
+pub trait BatchApi {
+	/// Creates and executes a batch from an uploaded file of requests.
+	fn batches_create(&self, batch_body: &BatchBody) -> ApiResult<Batch>;
+	/// Retrieves a batch.
+	fn batches_retrieve(&self, batch_id: &str) -> ApiResult<Batch>;
+	/// Lists your organization's batches, most recent first.
+	fn batches_list(&self, params: &ListParams) -> ApiResult<Page<Batch>>;
+	/// Cancels an in-progress batch. The batch will be in status `cancelling` for up to
+	/// 10 minutes, before changing to `cancelled`.
+	fn batches_cancel(&self, batch_id: &str) -> ApiResult<Batch>;
+	/// Downloads the output and error files of a finished batch and parses them with
+	/// [`parse_batch_output`].
+	fn batches_results<T: BatchRequest>(
+		&self,
+		batch: &Batch,
+	) -> ApiResult<HashMap<String, ApiResult<T::Response>>>;
+}
+
+impl BatchApi for OpenAI {
+	fn batches_create(&self, batch_body: &BatchBody) -> ApiResult<Batch> {
+		let request_body = to_json(batch_body)?;
+		let res = self.post(BATCHES, request_body)?;
+		let batch: Batch = from_json(&res)?;
+		Ok(batch)
+	}
+
+	fn batches_retrieve(&self, batch_id: &str) -> ApiResult<Batch> {
+		let res = self.get(&(BATCHES_RETRIEVE.to_owned() + batch_id))?;
+		let batch: Batch = from_json(&res)?;
+		Ok(batch)
+	}
+
+	fn batches_list(&self, params: &ListParams) -> ApiResult<Page<Batch>> {
+		let res = self.get(&params.url(BATCHES))?;
+		from_json(&res)
+	}
+
+	fn batches_cancel(&self, batch_id: &str) -> ApiResult<Batch> {
+		let sub_url = format!("{BATCHES_RETRIEVE}{batch_id}/cancel");
+		let res = self.post(&sub_url, Json::Object(Default::default()))?;
+		let batch: Batch = from_json(&res)?;
+		Ok(batch)
+	}
+
+	fn batches_results<T: BatchRequest>(
+		&self,
+		batch: &Batch,
+	) -> ApiResult<HashMap<String, ApiResult<T::Response>>> {
+		let mut results = HashMap::new();
+		for file_id in [&batch.output_file_id, &batch.error_file_id].into_iter().flatten() {
+			let mut content = Vec::new();
+			self.files_content(file_id, &mut content)?;
+			results.extend(parse_batch_output::<T>(&content)?);
+		}
+		Ok(results)
+	}
+}
+
+/// The async counterpart of [`BatchApi`].
+#[cfg(feature = "async")]
+pub trait AsyncBatchApi {
+	/// Creates and executes a batch from an uploaded file of requests.
+	fn batches_create(
+		&self,
+		batch_body: &BatchBody,
+	) -> impl Future<Output = ApiResult<Batch>> + Send;
+	/// Retrieves a batch.
+	fn batches_retrieve(&self, batch_id: &str) -> impl Future<Output = ApiResult<Batch>> + Send;
+	/// Lists your organization's batches, most recent first.
+	fn batches_list(
+		&self,
+		params: &ListParams,
+	) -> impl Future<Output = ApiResult<Page<Batch>>> + Send;
+	/// Cancels an in-progress batch. The batch will be in status `cancelling` for up to
+	/// 10 minutes, before changing to `cancelled`.
+	fn batches_cancel(&self, batch_id: &str) -> impl Future<Output = ApiResult<Batch>> + Send;
+	/// Downloads the output and error files of a finished batch and parses them with
+	/// [`parse_batch_output`].
+	fn batches_results<T: BatchRequest>(
+		&self,
+		batch: &Batch,
+	) -> impl Future<Output = ApiResult<HashMap<String, ApiResult<T::Response>>>> + Send;
+}
+
+#[cfg(feature = "async")]
+impl AsyncBatchApi for AsyncOpenAI {
+	async fn batches_create(&self, batch_body: &BatchBody) -> ApiResult<Batch> {
+		let request_body = to_json(batch_body)?;
+		let res = self.post(BATCHES, request_body).await?;
+		let batch: Batch = from_json(&res)?;
+		Ok(batch)
+	}
+
+	async fn batches_retrieve(&self, batch_id: &str) -> ApiResult<Batch> {
+		let res = self.get(&(BATCHES_RETRIEVE.to_owned() + batch_id)).await?;
+		let batch: Batch = from_json(&res)?;
+		Ok(batch)
+	}
+
+	async fn batches_list(&self, params: &ListParams) -> ApiResult<Page<Batch>> {
+		let res = self.get(&params.url(BATCHES)).await?;
+		from_json(&res)
+	}
+
+	async fn batches_cancel(&self, batch_id: &str) -> ApiResult<Batch> {
+		let sub_url = format!("{BATCHES_RETRIEVE}{batch_id}/cancel");
+		let res = self.post(&sub_url, Json::Object(Default::default())).await?;
+		let batch: Batch = from_json(&res)?;
+		Ok(batch)
+	}
+
+	async fn batches_results<T: BatchRequest>(
+		&self,
+		batch: &Batch,
+	) -> ApiResult<HashMap<String, ApiResult<T::Response>>> {
+		// Both files are downloaded before parsing, so that no response is held across an await.
+		let mut contents = Vec::new();
+		for file_id in [&batch.output_file_id, &batch.error_file_id].into_iter().flatten() {
+			let mut content = Vec::new();
+			AsyncFilesApi::files_content(self, file_id, &mut content).await?;
+			contents.push(content);
+		}
+		let mut results = HashMap::new();
+		for content in contents {
+			results.extend(parse_batch_output::<T>(&content)?);
+		}
+		Ok(results)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use serde_json::json;
+
+	use crate::{
+		apis::batches::{parse_batch_output, BatchApi, BatchBody, BatchInput, BatchStatus},
+		chat::ChatBody,
+		embeddings::EmbeddingsBody,
+		openai::new_mock_openai,
+		Message, MockResponse, Role,
+	};
+
+	fn batch(status: &str) -> serde_json::Value {
+		json!({
+			"id": "batch_abc123",
+			"object": "batch",
+			"endpoint": "/v1/embeddings",
+			"errors": null,
+			"input_file_id": "file-abc123",
+			"completion_window": "24h",
+			"status": status,
+			"output_file_id": "file-out",
+			"error_file_id": "file-err",
+			"created_at": 1711471533,
+			"request_counts": { "total": 2, "completed": 1, "failed": 1 },
+			"metadata": { "job": "nightly" }
+		})
+	}
+
+	#[test]
+	fn test_batch_input() {
+		let body = ChatBody {
+			model: "gpt-4o-mini".to_string(),
+			messages: vec![Message::new(Role::User, "Hello!")],
+			..Default::default()
+		};
+		let mut input = BatchInput::from_requests([("request-1", &body)]).unwrap();
+		assert!(input.add("request-1", &body).is_err());
+		assert_eq!(input.len(), 1);
+
+		let line: serde_json::Value = serde_json::from_slice(input.as_bytes()).unwrap();
+		assert_eq!(
+			line,
+			json!({
+				"custom_id": "request-1",
+				"method": "POST",
+				"url": "/v1/chat/completions",
+				"body": { "model": "gpt-4o-mini", "messages": [{ "role": "user", "content": "Hello!" }] }
+			})
+		);
+		assert!(input.as_bytes().ends_with(b"}\n"));
+	}
+
+	#[test]
+	fn test_parse_batch_output() {
+		let output = br#"{"id": "batch_req_1", "custom_id": "ok", "response": {"status_code": 200, "request_id": "req_1", "body": {"object": "list", "data": [{"object": "embedding", "embedding": [0.1], "index": 0}], "model": "text-embedding-3-small", "usage": {"prompt_tokens": 1, "total_tokens": 1}}}, "error": null}
+{"id": "batch_req_2", "custom_id": "bad", "response": {"status_code": 400, "request_id": "req_2", "body": {"error": {"message": "Invalid input", "type": "invalid_request_error"}}}, "error": null}
+{"id": "batch_req_3", "custom_id": "expired", "response": null, "error": {"code": "batch_expired", "message": "This request could not be executed before the completion window expired."}}
+"#;
+		let results = parse_batch_output::<EmbeddingsBody>(output).unwrap();
+		assert_eq!(results.len(), 3);
+		assert_eq!(results["ok"].as_ref().unwrap().model, "text-embedding-3-small");
+		match &results["bad"] {
+			Err(crate::Error::ApiError(e)) => {
+				assert_eq!(e.status, Some(400));
+				assert_eq!(e.request_id.as_deref(), Some("req_2"));
+			},
+			_ => panic!("expected an API error"),
+		}
+		match &results["expired"] {
+			Err(crate::Error::ApiError(e)) => assert_eq!(e.code.as_deref(), Some("batch_expired")),
+			_ => panic!("expected an API error"),
+		}
+	}
+
+	#[test]
+	fn test_batches() {
+		let (openai, transport) = new_mock_openai();
+		transport
+			.push(MockResponse::json(200, batch("validating")))
+			.push(MockResponse::json(200, batch("completed")))
+			.push(MockResponse::new(
+				200,
+				r#"{"custom_id": "a", "response": {"status_code": 200, "body": {"model": "m", "usage": {}}}}"#,
+			))
+			.push(MockResponse::new(
+				200,
+				r#"{"custom_id": "b", "response": null, "error": {"code": "batch_expired"}}"#,
+			))
+			.push(MockResponse::json(200, batch("cancelling")));
+		let body = BatchBody::new::<EmbeddingsBody>("file-abc123");
+		let created = openai.batches_create(&body).unwrap();
+		assert_eq!(created.status, BatchStatus::Validating);
+		let batch = openai.batches_retrieve(&created.id).unwrap();
+		assert!(batch.status.is_finished());
+		let results = openai.batches_results::<EmbeddingsBody>(&batch).unwrap();
+		assert!(results["a"].is_ok() && results["b"].is_err());
+		assert_eq!(openai.batches_cancel(&batch.id).unwrap().status, BatchStatus::Cancelling);
+
+		let requests = transport.requests();
+		assert_eq!(
+			requests[0].json().unwrap(),
+			json!({
+				"input_file_id": "file-abc123",
+				"endpoint": "/v1/embeddings",
+				"completion_window": "24h"
+			})
+		);
+		assert_eq!(requests[1].url, "https://api.openai.com/v1/batches/batch_abc123");
+		assert_eq!(requests[2].url, "https://api.openai.com/v1/files/file-out/content");
+		assert_eq!(requests[3].url, "https://api.openai.com/v1/files/file-err/content");
+		assert_eq!(requests[4].url, "https://api.openai.com/v1/batches/batch_abc123/cancel");
+	}
+}
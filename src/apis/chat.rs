@@ -5,13 +5,16 @@
 
 use std::collections::HashMap;
 
-use crate::requests::Requests;
+use crate::requests::{from_json, to_json, Requests};
 use crate::*;
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize, Serializer};
 
-use super::{completions::Completion, CHAT_COMPLETION_CREATE};
+use super::{completions::Completion, StreamOptions, Usage, CHAT_COMPLETION_CREATE};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[cfg(feature = "async")]
+use {crate::async_requests::AsyncRequests, std::future::Future};
+
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct ChatBody {
 	/// ID of the model to use.
 	/// See the model endpoint compatibility table for details on which models work with the Chat API.
@@ -42,6 +45,10 @@ pub struct ChatBody {
 	/// Defaults to false
 	#[serde(skip_serializing_if = "Option::is_none")]
 	pub stream: Option<bool>,
+	/// Options for streaming, e.g. to get the usage in the last chunk.
+	/// Defaults to null
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub stream_options: Option<StreamOptions>,
 	/// Up to 4 sequences where the API will stop generating further tokens.
 	/// Defaults to null
 	#[serde(skip_serializing_if = "Option::is_none")]
@@ -77,31 +84,392 @@ pub struct ChatBody {
 	/// which can help OpenAI to monitor and detect abuse. Learn more.
 	#[serde(skip_serializing_if = "Option::is_none")]
 	pub user: Option<String>,
+	/// A list of tools the model may call. Currently, only functions are supported as a tool.
+	/// Use this to provide a list of functions the model may generate JSON inputs for.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub tools: Option<Vec<Tool>>,
+	/// Controls which (if any) tool is called by the model.
+	/// Defaults to `none` when no tools are present, `auto` if tools are present.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub tool_choice: Option<ToolChoice>,
+	/// Whether to enable parallel function calling during tool use.
+	/// Defaults to true
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub parallel_tool_calls: Option<bool>,
+	/// An object specifying the format that the model must output.
+	/// `json_schema` enables Structured Outputs which ensures the model will match your supplied JSON schema.
+	/// `json_object` enables JSON mode, which ensures the message the model generates is valid JSON.
+	/// Defaults to text
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub response_format: Option<ResponseFormat>,
+	/// If specified, the system will make a best effort to sample deterministically,
+	/// such that repeated requests with the same seed and parameters should return the same result.
+	/// Determinism is not guaranteed.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub seed: Option<i64>,
+	/// Whether to return log probabilities of the output tokens or not.
+	/// If true, returns the log probabilities of each output token returned in the content of message.
+	/// Defaults to false
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub logprobs: Option<bool>,
+	/// An integer between 0 and 20 specifying the number of most likely tokens to return
+	/// at each token position, each with an associated log probability.
+	/// logprobs must be set to true if this parameter is used.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub top_logprobs: Option<i32>,
+}
+
+/// The format that the model must output.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ResponseFormat {
+	Text,
+	/// JSON mode. You must also instruct the model to produce JSON yourself via a system or user message.
+	JsonObject,
+	/// Structured Outputs, the model will match the supplied JSON schema.
+	JsonSchema {
+		json_schema: JsonSchema,
+	},
+}
+
+impl ResponseFormat {
+	/// Structured Outputs with strict schema adherence.
+	pub fn json_schema(name: &str, schema: Json) -> ResponseFormat {
+		ResponseFormat::JsonSchema {
+			json_schema: JsonSchema {
+				name: name.to_string(),
+				description: None,
+				schema: Some(schema),
+				strict: Some(true),
+			},
+		}
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JsonSchema {
+	/// The name of the response format. Must be a-z, A-Z, 0-9,
+	/// or contain underscores and dashes, with a maximum length of 64.
+	pub name: String,
+	/// A description of what the response format is for,
+	/// used by the model to determine how to respond in the format.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub description: Option<String>,
+	/// The schema for the response format, described as a JSON Schema object.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub schema: Option<Json>,
+	/// Whether to enable strict schema adherence when generating the output.
+	/// Only a subset of JSON Schema is supported when strict is true.
+	/// Defaults to false
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub strict: Option<bool>,
+}
+
+/// A tool the model may call.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Tool {
+	/// The type of the tool. Currently, only `function` is supported.
+	#[serde(rename = "type")]
+	pub tool_type: String,
+	pub function: FunctionDefinition,
+}
+
+impl Tool {
+	/// A `function` tool. `parameters` is the JSON Schema object describing the arguments.
+	pub fn function(name: &str, description: &str, parameters: Json) -> Tool {
+		Tool {
+			tool_type: "function".to_string(),
+			function: FunctionDefinition {
+				name: name.to_string(),
+				description: Some(description.to_string()),
+				parameters: Some(parameters),
+				strict: None,
+			},
+		}
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FunctionDefinition {
+	/// The name of the function to be called. Must be a-z, A-Z, 0-9,
+	/// or contain underscores and dashes, with a maximum length of 64.
+	pub name: String,
+	/// A description of what the function does,
+	/// used by the model to choose when and how to call the function.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub description: Option<String>,
+	/// The parameters the functions accepts, described as a JSON Schema object.
+	/// Omitting parameters defines a function with an empty parameter list.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub parameters: Option<Json>,
+	/// Whether to enable strict schema adherence when generating the function call.
+	/// Defaults to false
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub strict: Option<bool>,
+}
+
+/// Controls which (if any) tool is called by the model.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ToolChoice {
+	/// The model will not call any tool and instead generates a message.
+	None,
+	/// The model can pick between generating a message or calling one or more tools.
+	Auto,
+	/// The model must call one or more tools.
+	Required,
+	/// Forces the model to call the named function.
+	Function(String),
+}
+
+impl Serialize for ToolChoice {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		match self {
+			Self::None => serializer.serialize_str("none"),
+			Self::Auto => serializer.serialize_str("auto"),
+			Self::Required => serializer.serialize_str("required"),
+			Self::Function(name) => serde_json::json!({
+				"type": "function",
+				"function": { "name": name },
+			})
+			.serialize(serializer),
+		}
+	}
+}
+
+impl<'de> Deserialize<'de> for ToolChoice {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		let value = Json::deserialize(deserializer)?;
+		match value.as_str() {
+			Some("none") => return Ok(Self::None),
+			Some("auto") => return Ok(Self::Auto),
+			Some("required") => return Ok(Self::Required),
+			_ => {},
+		}
+		match value.pointer("/function/name").and_then(Json::as_str) {
+			Some(name) => Ok(Self::Function(name.to_string())),
+			None => Err(serde::de::Error::custom(format!("invalid tool_choice: {value}"))),
+		}
+	}
+}
+
+/// A chunk of a streamed chat completion, sent as a server-sent event.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChatCompletionChunk {
+	pub id: Option<String>,
+	pub object: Option<String>,
+	pub created: Option<u64>,
+	pub model: Option<String>,
+	pub choices: Vec<ChunkChoice>,
+	/// Only present on the last chunk, when requested through `stream_options`.
+	pub usage: Option<Usage>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChunkChoice {
+	pub index: u32,
+	pub delta: Delta,
+	/// Log probability information of the delta, when requested through `logprobs`.
+	pub logprobs: Option<Json>,
+	pub finish_reason: Option<String>,
+}
+
+/// The part of the message generated since the previous chunk.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Delta {
+	pub role: Option<Role>,
+	pub content: Option<String>,
+	pub tool_calls: Option<Vec<ToolCallDelta>>,
+}
+
+/// The part of a tool call generated since the previous chunk.
+/// The `id` and function `name` are only sent in the first delta of each call,
+/// the `arguments` are split across deltas sharing the same `index`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ToolCallDelta {
+	pub index: u32,
+	pub id: Option<String>,
+	#[serde(rename = "type")]
+	pub tool_type: Option<String>,
+	pub function: Option<FunctionCallDelta>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FunctionCallDelta {
+	pub name: Option<String>,
+	pub arguments: Option<String>,
+}
+
+impl Completion {
+	/// Deserializes the JSON content of the first choice's message into `T`.
+	///
+	/// Returns `Error::Refusal` if the model refused to answer,
+	/// and `Error::ParseError` if the content is missing or does not match `T`.
+	pub fn parse_message<T: DeserializeOwned>(&self) -> ApiResult<T> {
+		let choice = self
+			.choices
+			.first()
+			.ok_or_else(|| Error::ParseError("No choice in completion".to_string()))?;
+		let message = choice
+			.message
+			.as_ref()
+			.ok_or_else(|| Error::ParseError("No message in completion".to_string()))?;
+		if let Some(refusal) = &message.refusal {
+			return Err(Error::Refusal(refusal.clone()));
+		}
+		let content = message
+			.text()
+			.ok_or_else(|| Error::ParseError("No text content in message".to_string()))?;
+		serde_json::from_str(content).map_err(|e| {
+			let finish_reason = choice.finish_reason.as_deref().unwrap_or_default();
+			Error::ParseError(format!("{e}, finish_reason: {finish_reason}, content: {content}"))
+		})
+	}
+}
+
+/// Checks the answer of `chat_body` can be parsed, i.e. it asks for Structured Outputs.
+fn check_json_schema(chat_body: &ChatBody) -> ApiResult<()> {
+	match chat_body.response_format {
+		Some(ResponseFormat::JsonSchema { .. }) => Ok(()),
+		_ => Err(Error::InvalidInput(
+			"response_format must be a JSON schema to parse the answer".to_string(),
+		)),
+	}
 }
 
 pub trait ChatApi {
 	/// Creates a completion for the chat message
 	fn chat_completion_create(&self, chat_body: &ChatBody) -> ApiResult<Completion>;
+	/// Creates a completion for the chat message and deserializes the answer into `T`.
+	/// `chat_body.response_format` must be a JSON schema matching `T`, see [`ResponseFormat::json_schema`],
+	/// otherwise [`Error::InvalidInput`] is returned without sending the request.
+	fn chat_completion_parse<T: DeserializeOwned>(&self, chat_body: &ChatBody) -> ApiResult<T>
+	where
+		Self: Sized,
+	{
+		check_json_schema(chat_body)?;
+		self.chat_completion_create(chat_body)?.parse_message()
+	}
+	/// Creates a completion for the chat message, streaming back partial message deltas
+	/// as they become available. `chat_body.stream` is ignored.
+	fn chat_completion_stream(
+		&self,
+		chat_body: &ChatBody,
+	) -> ApiResult<EventStream<ChatCompletionChunk>>;
 }
 
 impl ChatApi for OpenAI {
 	fn chat_completion_create(&self, chat_body: &ChatBody) -> ApiResult<Completion> {
-		let request_body = serde_json::to_value(chat_body).unwrap();
+		let request_body = to_json(chat_body)?;
 		let res = self.post(CHAT_COMPLETION_CREATE, request_body)?;
-		let completion: Completion = serde_json::from_value(res.clone()).unwrap();
+		let completion: Completion = from_json(&res)?;
+		Ok(completion)
+	}
+
+	fn chat_completion_stream(
+		&self,
+		chat_body: &ChatBody,
+	) -> ApiResult<EventStream<ChatCompletionChunk>> {
+		let mut request_body = to_json(chat_body)?;
+		request_body["stream"] = Json::Bool(true);
+		let reader = self.post_stream(CHAT_COMPLETION_CREATE, request_body)?;
+		Ok(EventStream::new(reader))
+	}
+}
+
+/// The async counterpart of [`ChatApi`].
+#[cfg(feature = "async")]
+pub trait AsyncChatApi {
+	/// Creates a completion for the chat message
+	fn chat_completion_create(
+		&self,
+		chat_body: &ChatBody,
+	) -> impl Future<Output = ApiResult<Completion>> + Send;
+	/// Creates a completion for the chat message, streaming back partial message deltas
+	/// as they become available. `chat_body.stream` is ignored.
+	fn chat_completion_stream(
+		&self,
+		chat_body: &ChatBody,
+	) -> impl Future<Output = ApiResult<AsyncEventStream<ChatCompletionChunk>>> + Send;
+	/// Creates a completion for the chat message and deserializes the answer into `T`.
+	/// `chat_body.response_format` must be a JSON schema matching `T`, see [`ResponseFormat::json_schema`],
+	/// otherwise [`Error::InvalidInput`] is returned without sending the request.
+	fn chat_completion_parse<T: DeserializeOwned>(
+		&self,
+		chat_body: &ChatBody,
+	) -> impl Future<Output = ApiResult<T>> + Send;
+}
+
+#[cfg(feature = "async")]
+impl AsyncChatApi for AsyncOpenAI {
+	async fn chat_completion_create(&self, chat_body: &ChatBody) -> ApiResult<Completion> {
+		let request_body = to_json(chat_body)?;
+		let res = self.post(CHAT_COMPLETION_CREATE, request_body).await?;
+		let completion: Completion = from_json(&res)?;
 		Ok(completion)
 	}
+
+	async fn chat_completion_stream(
+		&self,
+		chat_body: &ChatBody,
+	) -> ApiResult<AsyncEventStream<ChatCompletionChunk>> {
+		let mut request_body = to_json(chat_body)?;
+		request_body["stream"] = Json::Bool(true);
+		let response = self.post_stream(CHAT_COMPLETION_CREATE, request_body).await?;
+		Ok(AsyncEventStream::new(response))
+	}
+
+	async fn chat_completion_parse<T: DeserializeOwned>(
+		&self,
+		chat_body: &ChatBody,
+	) -> ApiResult<T> {
+		check_json_schema(chat_body)?;
+		self.chat_completion_create(chat_body).await?.parse_message()
+	}
 }
 
 #[cfg(test)]
 mod tests {
-	use crate::{apis::chat::ChatBody, openai::new_test_openai, Message, Role};
+	use serde::Deserialize;
+	use serde_json::json;
+
+	use crate::{
+		apis::chat::ChatBody, completions::Completion, openai::new_mock_openai, ContentPart, Error,
+		ImageDetail, Message, MockResponse, Role, StreamOptions,
+	};
 
-	use super::ChatApi;
+	use super::{ChatApi, ResponseFormat, Tool, ToolChoice};
+
+	/// A chat completion response with a choice per message.
+	fn completion_response(
+		messages: &[serde_json::Value],
+		finish_reason: &str,
+	) -> serde_json::Value {
+		let choices: Vec<_> = messages
+			.iter()
+			.enumerate()
+			.map(|(index, message)| {
+				json!({ "index": index, "message": message, "logprobs": null, "finish_reason": finish_reason })
+			})
+			.collect();
+		json!({
+			"id": "chatcmpl-123",
+			"object": "chat.completion",
+			"created": 1677652288,
+			"model": "gpt-3.5-turbo-0125",
+			"choices": choices,
+			"usage": { "prompt_tokens": 9, "completion_tokens": 12, "total_tokens": 21 }
+		})
+	}
 
 	#[test]
 	fn test_chat_completion() {
-		let openai = new_test_openai();
+		let (openai, transport) = new_mock_openai();
+		let message =
+			json!({ "role": "assistant", "content": "Hello! How can I", "refusal": null });
+		transport.push(MockResponse::json(
+			200,
+			completion_response(&[message.clone(), message], "length"),
+		));
 		let body = ChatBody {
 			model: "gpt-3.5-turbo".to_string(),
 			max_tokens: Some(7),
@@ -114,11 +482,419 @@ mod tests {
 			frequency_penalty: None,
 			logit_bias: None,
 			user: None,
-			messages: vec![Message { role: Role::User, content: "Hello!".to_string() }],
+			messages: vec![Message::new(Role::User, "Hello!")],
+			..Default::default()
 		};
 		let rs = openai.chat_completion_create(&body);
 		let choice = rs.unwrap().choices;
+		assert_eq!(choice.len(), 2);
 		let message = &choice[0].message.as_ref().unwrap();
-		assert!(message.content.contains("Hello"));
+		assert!(message.text().unwrap().contains("Hello"));
+		assert_eq!(choice[1].finish_reason.as_deref(), Some("length"));
+
+		let requests = transport.requests();
+		assert_eq!(requests.len(), 1);
+		assert_eq!(requests[0].method, "POST");
+		assert_eq!(requests[0].url, "https://api.openai.com/v1/chat/completions");
+		assert_eq!(requests[0].header("authorization"), Some("Bearer sk-test"));
+		assert_eq!(
+			requests[0].json().unwrap(),
+			json!({
+				"model": "gpt-3.5-turbo",
+				"messages": [{ "role": "user", "content": "Hello!" }],
+				"max_tokens": 7,
+				"temperature": 0.0,
+				"top_p": 0.0,
+				"n": 2,
+				"stream": false
+			})
+		);
+	}
+
+	#[test]
+	fn test_chat_completion_stream_mock() {
+		let (openai, transport) = new_mock_openai();
+		let chunk = |content: &str| {
+			json!({
+				"id": "chatcmpl-123",
+				"object": "chat.completion.chunk",
+				"created": 1677652288,
+				"model": "gpt-4o-mini",
+				"choices": [{ "index": 0, "delta": { "content": content }, "finish_reason": null }]
+			})
+		};
+		transport.push(MockResponse::events(&[chunk("Hello"), chunk(" there!")]));
+		let body = ChatBody {
+			model: "gpt-4o-mini".to_string(),
+			messages: vec![Message::new(Role::User, "Hello!")],
+			..Default::default()
+		};
+		let content: String = openai
+			.chat_completion_stream(&body)
+			.unwrap()
+			.map(|chunk| chunk.unwrap().choices[0].delta.content.clone().unwrap_or_default())
+			.collect();
+		assert_eq!(content, "Hello there!");
+		let request = &transport.requests()[0];
+		assert_eq!(request.header("accept"), Some("text/event-stream"));
+		assert_eq!(request.json().unwrap()["stream"], true);
+	}
+
+	#[test]
+	fn test_chat_completion_stream_usage() {
+		let (openai, transport) = new_mock_openai();
+		let logprobs =
+			json!({ "content": [{ "token": "Hi", "logprob": -0.1, "top_logprobs": [] }] });
+		transport.push(MockResponse::events(&[
+			json!({
+				"id": "chatcmpl-123",
+				"object": "chat.completion.chunk",
+				"model": "gpt-4o-mini",
+				"choices": [{
+					"index": 0,
+					"delta": { "content": "Hi" },
+					"logprobs": logprobs,
+					"finish_reason": "stop"
+				}],
+				"usage": null
+			}),
+			json!({
+				"id": "chatcmpl-123",
+				"object": "chat.completion.chunk",
+				"model": "gpt-4o-mini",
+				"choices": [],
+				"usage": { "prompt_tokens": 9, "completion_tokens": 1, "total_tokens": 10 }
+			}),
+		]));
+		let body = ChatBody {
+			model: "gpt-4o-mini".to_string(),
+			messages: vec![Message::new(Role::User, "Hello!")],
+			logprobs: Some(true),
+			stream_options: Some(StreamOptions { include_usage: true }),
+			..Default::default()
+		};
+		let chunks: Vec<_> =
+			openai.chat_completion_stream(&body).unwrap().map(|chunk| chunk.unwrap()).collect();
+		assert_eq!(chunks.len(), 2);
+		assert_eq!(chunks[0].choices[0].logprobs, Some(logprobs));
+		assert!(chunks[0].usage.is_none());
+		assert!(chunks[1].choices.is_empty());
+		assert_eq!(chunks[1].usage.as_ref().unwrap().total_tokens, Some(10));
+
+		let request = transport.requests()[0].json().unwrap();
+		assert_eq!(request["stream_options"], json!({ "include_usage": true }));
+		assert_eq!(request["logprobs"], true);
+	}
+
+	#[test]
+	fn test_chat_completion_tool_calls() {
+		let (openai, transport) = new_mock_openai();
+		let message = json!({
+			"role": "assistant",
+			"content": null,
+			"tool_calls": [{
+				"id": "call_abc123",
+				"type": "function",
+				"function": { "name": "get_weather", "arguments": "{\"city\":\"Paris\"}" }
+			}]
+		});
+		transport.push(MockResponse::json(200, completion_response(&[message], "tool_calls")));
+		let body = ChatBody {
+			model: "gpt-3.5-turbo".to_string(),
+			temperature: Some(0_f32),
+			messages: vec![Message::new(Role::User, "What's the weather like in Paris?")],
+			tools: Some(vec![Tool::function(
+				"get_weather",
+				"Get the current weather in a given city",
+				json!({
+					"type": "object",
+					"properties": { "city": { "type": "string" } },
+					"required": ["city"],
+				}),
+			)]),
+			tool_choice: Some(ToolChoice::Function("get_weather".to_string())),
+			..Default::default()
+		};
+		let rs = openai.chat_completion_create(&body);
+		let choices = rs.unwrap().choices;
+		let tool_calls = choices[0].tool_calls();
+		assert_eq!(tool_calls[0].function.name, "get_weather");
+		let args: serde_json::Value = tool_calls[0].function.parse_arguments().unwrap();
+		assert_eq!(args["city"], "Paris");
+
+		let request = transport.requests()[0].json().unwrap();
+		assert_eq!(
+			request["tool_choice"],
+			json!({ "type": "function", "function": { "name": "get_weather" } })
+		);
+		assert_eq!(request["tools"][0]["type"], "function");
+		assert_eq!(request["tools"][0]["function"]["name"], "get_weather");
+		assert_eq!(request["tools"][0]["function"]["parameters"]["required"], json!(["city"]));
+	}
+
+	#[test]
+	fn test_tool_choice_serde() {
+		let choices = vec![
+			ToolChoice::None,
+			ToolChoice::Auto,
+			ToolChoice::Required,
+			ToolChoice::Function("get_weather".to_string()),
+		];
+		let value = serde_json::to_value(&choices).unwrap();
+		assert_eq!(
+			value,
+			json!(["none", "auto", "required", {"type": "function", "function": {"name": "get_weather"}}])
+		);
+		let parsed: Vec<ToolChoice> = serde_json::from_value(value).unwrap();
+		assert_eq!(parsed, choices);
+	}
+
+	#[test]
+	fn test_tool_call_message() {
+		let completion: Completion = serde_json::from_value(json!({
+			"id": "chatcmpl-1",
+			"object": "chat.completion",
+			"created": 1,
+			"model": "gpt-3.5-turbo",
+			"choices": [{
+				"index": 0,
+				"finish_reason": "tool_calls",
+				"message": {
+					"role": "assistant",
+					"content": null,
+					"tool_calls": [{
+						"id": "call_1",
+						"type": "function",
+						"function": { "name": "get_weather", "arguments": "{\"city\":\"Paris\"}" }
+					}]
+				}
+			}],
+			"usage": { "prompt_tokens": 1, "completion_tokens": 1, "total_tokens": 2 }
+		}))
+		.unwrap();
+		let message = completion.choices[0].message.clone().unwrap();
+		assert!(message.content.is_none());
+		let tool_call = &completion.choices[0].tool_calls()[0];
+		assert_eq!(tool_call.id, "call_1");
+		let args: serde_json::Value = tool_call.function.parse_arguments().unwrap();
+		assert_eq!(args, json!({"city": "Paris"}));
+
+		let reply = serde_json::to_value(Message::tool("call_1", "Sunny")).unwrap();
+		assert_eq!(reply, json!({"role": "tool", "content": "Sunny", "tool_call_id": "call_1"}));
+	}
+
+	#[test]
+	fn test_chat_completion_vision() {
+		let (openai, transport) = new_mock_openai();
+		let message = json!({ "role": "assistant", "content": "No." });
+		transport.push(MockResponse::json(200, completion_response(&[message], "stop")));
+		let body = ChatBody {
+			model: "gpt-4o-mini".to_string(),
+			max_tokens: Some(20),
+			temperature: Some(0_f32),
+			messages: vec![Message::with_parts(
+				Role::User,
+				vec![
+					ContentPart::text("Is this a picture of an animal? Answer yes or no."),
+					ContentPart::image_file("test_files/image.png", Some(ImageDetail::Low))
+						.unwrap(),
+				],
+			)],
+			..Default::default()
+		};
+		let rs = openai.chat_completion_create(&body);
+		let choice = rs.unwrap().choices;
+		let message = &choice[0].message.as_ref().unwrap();
+		assert_eq!(message.text(), Some("No."));
+
+		let request = transport.requests()[0].json().unwrap();
+		let parts = &request["messages"][0]["content"];
+		assert_eq!(parts[0]["type"], "text");
+		assert_eq!(parts[1]["type"], "image_url");
+		assert_eq!(parts[1]["image_url"]["detail"], "low");
+		let url = parts[1]["image_url"]["url"].as_str().unwrap();
+		assert!(url.starts_with("data:image/png;base64,iVBORw0KGgo"));
+	}
+
+	#[test]
+	fn test_content_parts_serde() {
+		let message = Message::with_parts(
+			Role::User,
+			vec![
+				ContentPart::text("What's in this image?"),
+				ContentPart::image_url("https://example.com/cat.png", Some(ImageDetail::High)),
+				ContentPart::image_bytes(b"foo", &mime::IMAGE_PNG, None),
+			],
+		);
+		let value = serde_json::to_value(&message).unwrap();
+		assert_eq!(
+			value,
+			json!({
+				"role": "user",
+				"content": [
+					{ "type": "text", "text": "What's in this image?" },
+					{ "type": "image_url", "image_url": { "url": "https://example.com/cat.png", "detail": "high" } },
+					{ "type": "image_url", "image_url": { "url": "data:image/png;base64,Zm9v" } },
+				],
+			})
+		);
+		let parsed: Message = serde_json::from_value(value).unwrap();
+		assert!(parsed.text().is_none());
+
+		let text = Message::new(Role::User, "Hello!");
+		assert_eq!(
+			serde_json::to_value(&text).unwrap(),
+			json!({"role": "user", "content": "Hello!"})
+		);
+		let rs = ContentPart::image_file("test_files/audio.mp3", None);
+		assert!(matches!(rs, Err(Error::InvalidInput(_))));
+	}
+
+	#[derive(Debug, Deserialize)]
+	struct Answer {
+		city: String,
+		country: String,
+	}
+
+	fn answer_format() -> ResponseFormat {
+		ResponseFormat::json_schema(
+			"answer",
+			json!({
+				"type": "object",
+				"properties": {
+					"city": { "type": "string" },
+					"country": { "type": "string" },
+				},
+				"required": ["city", "country"],
+				"additionalProperties": false,
+			}),
+		)
+	}
+
+	#[test]
+	fn test_chat_completion_parse() {
+		let (openai, transport) = new_mock_openai();
+		let message = json!({ "role": "assistant", "content": "{\"city\":\"Paris\",\"country\":\"France\"}" });
+		transport.push(MockResponse::json(200, completion_response(&[message], "stop")));
+		let body = ChatBody {
+			model: "gpt-4o-mini".to_string(),
+			temperature: Some(0_f32),
+			seed: Some(42),
+			messages: vec![Message::new(Role::User, "Where is the Eiffel Tower?")],
+			response_format: Some(answer_format()),
+			..Default::default()
+		};
+		let answer: Answer = openai.chat_completion_parse(&body).unwrap();
+		assert_eq!(answer.city, "Paris");
+		assert_eq!(answer.country, "France");
+
+		let request = transport.requests()[0].json().unwrap();
+		assert_eq!(request["seed"], 42);
+		assert_eq!(request["response_format"], serde_json::to_value(answer_format()).unwrap());
+	}
+
+	#[test]
+	fn test_chat_completion_parse_without_schema() {
+		let (openai, transport) = new_mock_openai();
+		let body = ChatBody {
+			model: "gpt-4o-mini".to_string(),
+			messages: vec![Message::new(Role::User, "Where is the Eiffel Tower?")],
+			response_format: Some(ResponseFormat::JsonObject),
+			..Default::default()
+		};
+		let answer = openai.chat_completion_parse::<Answer>(&body);
+		assert!(matches!(answer, Err(Error::InvalidInput(_))));
+		let body = ChatBody { response_format: None, ..body };
+		let answer = openai.chat_completion_parse::<Answer>(&body);
+		assert!(matches!(answer, Err(Error::InvalidInput(_))));
+		assert!(transport.requests().is_empty());
+	}
+
+	#[test]
+	fn test_response_format_serde() {
+		let value = serde_json::to_value(answer_format()).unwrap();
+		assert_eq!(value["type"], "json_schema");
+		assert_eq!(value["json_schema"]["name"], "answer");
+		assert_eq!(value["json_schema"]["strict"], true);
+		assert_eq!(
+			serde_json::to_value(ResponseFormat::JsonObject).unwrap(),
+			json!({"type": "json_object"})
+		);
+	}
+
+	#[test]
+	fn test_parse_message() {
+		let completion = |message: serde_json::Value| -> Completion {
+			serde_json::from_value(json!({
+				"choices": [{ "index": 0, "finish_reason": "stop", "message": message }],
+				"usage": {},
+			}))
+			.unwrap()
+		};
+		let answer: Answer = completion(json!({
+			"role": "assistant",
+			"content": "{\"city\": \"Paris\", \"country\": \"France\"}",
+		}))
+		.parse_message()
+		.unwrap();
+		assert_eq!(answer.city, "Paris");
+
+		let refused = completion(json!({
+			"role": "assistant",
+			"content": null,
+			"refusal": "I'm sorry, I cannot help with that.",
+		}))
+		.parse_message::<Answer>();
+		assert!(matches!(refused, Err(Error::Refusal(_))));
+
+		let invalid =
+			completion(json!({ "role": "assistant", "content": "{\"city\": \"Paris\"}" }))
+				.parse_message::<Answer>();
+		assert!(matches!(invalid, Err(Error::ParseError(_))));
+	}
+
+	#[cfg(feature = "async")]
+	#[tokio::test]
+	async fn test_async_chat_completion() {
+		use crate::{apis::chat::AsyncChatApi, openai::new_mock_async_openai};
+
+		let (openai, transport) = new_mock_async_openai();
+		let message = json!({ "role": "assistant", "content": "Hello! How can I" });
+		transport.push(MockResponse::json(200, completion_response(&[message], "length")));
+		let chunk = |content: &str| {
+			json!({
+				"id": "chatcmpl-123",
+				"object": "chat.completion.chunk",
+				"created": 1677652288,
+				"model": "gpt-3.5-turbo-0125",
+				"choices": [{ "index": 0, "delta": { "content": content }, "finish_reason": null }]
+			})
+		};
+		transport.push(MockResponse::events(&[chunk("Hello"), chunk(" there!")]));
+		let body = ChatBody {
+			model: "gpt-3.5-turbo".to_string(),
+			max_tokens: Some(7),
+			temperature: Some(0_f32),
+			messages: vec![Message::new(Role::User, "Hello!")],
+			..Default::default()
+		};
+		let rs = openai.chat_completion_create(&body).await;
+		let choice = rs.unwrap().choices;
+		let message = &choice[0].message.as_ref().unwrap();
+		assert!(message.text().unwrap().contains("Hello"));
+
+		let mut stream = openai.chat_completion_stream(&body).await.unwrap();
+		let mut content = String::new();
+		while let Some(chunk) = stream.next().await {
+			for choice in chunk.unwrap().choices {
+				content.push_str(&choice.delta.content.unwrap_or_default());
+			}
+		}
+		assert_eq!(content, "Hello there!");
+		assert_eq!(transport.requests()[1].header("accept"), Some("text/event-stream"));
+
+		let answer = openai.chat_completion_parse::<Answer>(&body).await;
+		assert!(matches!(answer, Err(Error::InvalidInput(_))));
+		assert_eq!(transport.requests().len(), 2);
 	}
 }
@@ -6,11 +6,14 @@
 
 use std::collections::HashMap;
 
-use crate::requests::Requests;
+use crate::requests::{from_json, to_json, Requests};
 use crate::*;
 use serde::{Deserialize, Serialize};
 
-use super::{Usage, COMPLETION_CREATE};
+use super::{StreamOptions, Usage, COMPLETION_CREATE};
+
+#[cfg(feature = "async")]
+use {crate::async_requests::AsyncRequests, std::future::Future};
 
 /// Given a prompt, the model will return one or more predicted completions,
 /// and can also return the probabilities of alternative tokens at each position.
@@ -24,6 +27,83 @@ pub struct Completion {
 	pub usage: Usage,
 }
 
+/// A chunk of a streamed completion, sent as a server-sent event.
+/// Each choice carries the text generated since the previous chunk.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CompletionChunk {
+	pub id: Option<String>,
+	pub object: Option<String>,
+	pub created: Option<u64>,
+	pub model: Option<String>,
+	pub choices: Vec<Choice>,
+	/// Only present on the last chunk, when requested through `stream_options`.
+	pub usage: Option<Usage>,
+}
+
+impl Completion {
+	/// Collects the chunks of a streamed completion back into a full completion,
+	/// concatenating the text fragments and log probabilities of each choice.
+	/// The usage is only known when requested through `stream_options`.
+	/// Returns the first error encountered in the stream.
+	pub fn from_chunks<I>(chunks: I) -> ApiResult<Completion>
+	where
+		I: IntoIterator<Item = ApiResult<CompletionChunk>>,
+	{
+		let mut completion = Completion {
+			id: None,
+			object: None,
+			created: None,
+			model: None,
+			choices: vec![],
+			usage: Usage::default(),
+		};
+		for chunk in chunks {
+			let chunk = chunk?;
+			completion.id = completion.id.or(chunk.id);
+			completion.object = completion.object.or(chunk.object);
+			completion.created = completion.created.or(chunk.created);
+			completion.model = completion.model.or(chunk.model);
+			if let Some(usage) = chunk.usage {
+				completion.usage = usage;
+			}
+			for choice in chunk.choices {
+				match completion.choices.iter_mut().find(|c| c.index == choice.index) {
+					Some(merged) => {
+						if let Some(text) = choice.text {
+							merged.text.get_or_insert_with(String::new).push_str(&text);
+						}
+						merge_logprobs(&mut merged.logprobs, choice.logprobs);
+						if choice.finish_reason.is_some() {
+							merged.finish_reason = choice.finish_reason;
+						}
+					},
+					None => completion.choices.push(choice),
+				}
+			}
+		}
+		completion.choices.sort_by_key(|c| c.index);
+		Ok(completion)
+	}
+}
+
+/// Appends the tokens, log probabilities and offsets of a chunk to those of the previous ones.
+fn merge_logprobs(merged: &mut Option<Json>, logprobs: Option<Json>) {
+	match (merged.as_mut(), logprobs) {
+		(_, None) => {},
+		(Some(Json::Object(merged)), Some(Json::Object(logprobs))) => {
+			for (key, value) in logprobs {
+				match (merged.get_mut(&key), value) {
+					(Some(Json::Array(merged)), Json::Array(values)) => merged.extend(values),
+					(_, value) => {
+						merged.insert(key, value);
+					},
+				}
+			}
+		},
+		(_, logprobs) => *merged = logprobs,
+	}
+}
+
 /// Request body for `Create completion` API
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CompletionsBody {
@@ -71,6 +151,10 @@ pub struct CompletionsBody {
 	/// Defaults to false
 	#[serde(skip_serializing_if = "Option::is_none")]
 	pub stream: Option<bool>,
+	/// Options for streaming, e.g. to get the usage in the last chunk.
+	/// Defaults to null
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub stream_options: Option<StreamOptions>,
 	/// Include the log probabilities on the logprobs most likely tokens,
 	/// as well the chosen tokens. For example, if logprobs is 5,
 	/// the API will return a list of the 5 most likely tokens.
@@ -129,26 +213,99 @@ pub struct CompletionsBody {
 pub trait CompletionsApi {
 	/// Creates a completion for the provided prompt and parameters
 	fn completion_create(&self, completions_body: &CompletionsBody) -> ApiResult<Completion>;
+	/// Creates a completion for the provided prompt and parameters,
+	/// streaming back partial progress as it becomes available. `completions_body.stream` is ignored.
+	/// The chunks can be collected back with [`Completion::from_chunks`].
+	fn completion_stream(
+		&self,
+		completions_body: &CompletionsBody,
+	) -> ApiResult<EventStream<CompletionChunk>>;
 }
 
 impl CompletionsApi for OpenAI {
 	fn completion_create(&self, completions_body: &CompletionsBody) -> ApiResult<Completion> {
-		let request_body = serde_json::to_value(completions_body).unwrap();
+		let request_body = to_json(completions_body)?;
 		let res = self.post(COMPLETION_CREATE, request_body)?;
-		let completion: Completion = serde_json::from_value(res.clone()).unwrap();
+		let completion: Completion = from_json(&res)?;
 		Ok(completion)
 	}
+
+	fn completion_stream(
+		&self,
+		completions_body: &CompletionsBody,
+	) -> ApiResult<EventStream<CompletionChunk>> {
+		let mut request_body = to_json(completions_body)?;
+		request_body["stream"] = Json::Bool(true);
+		let reader = self.post_stream(COMPLETION_CREATE, request_body)?;
+		Ok(EventStream::new(reader))
+	}
+}
+
+/// The async counterpart of [`CompletionsApi`].
+#[cfg(feature = "async")]
+pub trait AsyncCompletionsApi {
+	/// Creates a completion for the provided prompt and parameters
+	fn completion_create(
+		&self,
+		completions_body: &CompletionsBody,
+	) -> impl Future<Output = ApiResult<Completion>> + Send;
+	/// Creates a completion for the provided prompt and parameters,
+	/// streaming back partial progress as it becomes available. `completions_body.stream` is ignored.
+	fn completion_stream(
+		&self,
+		completions_body: &CompletionsBody,
+	) -> impl Future<Output = ApiResult<AsyncEventStream<CompletionChunk>>> + Send;
+}
+
+#[cfg(feature = "async")]
+impl AsyncCompletionsApi for AsyncOpenAI {
+	async fn completion_create(&self, completions_body: &CompletionsBody) -> ApiResult<Completion> {
+		let request_body = to_json(completions_body)?;
+		let res = self.post(COMPLETION_CREATE, request_body).await?;
+		let completion: Completion = from_json(&res)?;
+		Ok(completion)
+	}
+
+	async fn completion_stream(
+		&self,
+		completions_body: &CompletionsBody,
+	) -> ApiResult<AsyncEventStream<CompletionChunk>> {
+		let mut request_body = to_json(completions_body)?;
+		request_body["stream"] = Json::Bool(true);
+		let response = self.post_stream(COMPLETION_CREATE, request_body).await?;
+		Ok(AsyncEventStream::new(response))
+	}
 }
 
 #[cfg(test)]
 mod tests {
-	use crate::openai::new_test_openai;
+	use serde_json::json;
 
-	use super::{CompletionsApi, CompletionsBody};
+	use crate::{openai::new_mock_openai, Choice, MockResponse, StreamOptions, Usage};
+
+	use super::{Completion, CompletionChunk, CompletionsApi, CompletionsBody};
+
+	fn completion_choice(index: u32, text: &str, finish_reason: Option<&str>) -> serde_json::Value {
+		json!({ "text": text, "index": index, "logprobs": null, "finish_reason": finish_reason })
+	}
 
 	#[test]
 	fn test_completions() {
-		let openai = new_test_openai();
+		let (openai, transport) = new_mock_openai();
+		transport.push(MockResponse::json(
+			200,
+			json!({
+				"id": "cmpl-uqkvlQyYK7bGYrRHQ0eXlWi7",
+				"object": "text_completion",
+				"created": 1589478378,
+				"model": "babbage-002",
+				"choices": [
+					completion_choice(0, " this is a test", Some("length")),
+					completion_choice(1, " this is a test", Some("length"))
+				],
+				"usage": { "prompt_tokens": 5, "completion_tokens": 14, "total_tokens": 19 }
+			}),
+		));
 		let body = CompletionsBody {
 			model: "babbage-002".to_string(),
 			prompt: Some(vec!["Say this is a test".to_string()]),
@@ -158,6 +315,7 @@ mod tests {
 			top_p: Some(0_f32),
 			n: Some(2),
 			stream: Some(false),
+			stream_options: None,
 			logprobs: None,
 			echo: None,
 			stop: Some(vec!["\n".to_string()]),
@@ -171,5 +329,140 @@ mod tests {
 		let choice = rs.unwrap().choices;
 		let text = &choice[0].text.as_ref().unwrap();
 		assert!(text.contains("this"));
+
+		let request = &transport.requests()[0];
+		assert_eq!(request.url, "https://api.openai.com/v1/completions");
+		assert_eq!(
+			request.json().unwrap(),
+			json!({
+				"model": "babbage-002",
+				"prompt": ["Say this is a test"],
+				"max_tokens": 7,
+				"temperature": 0.0,
+				"top_p": 0.0,
+				"n": 2,
+				"stream": false,
+				"stop": ["\n"]
+			})
+		);
+	}
+
+	#[test]
+	fn test_completion_stream() {
+		let (openai, transport) = new_mock_openai();
+		let chunk = |index, text, finish_reason| {
+			json!({
+				"id": "cmpl-uqkvlQyYK7bGYrRHQ0eXlWi7",
+				"object": "text_completion",
+				"created": 1589478378,
+				"model": "babbage-002",
+				"choices": [completion_choice(index, text, finish_reason)]
+			})
+		};
+		transport.push(MockResponse::events(&[
+			chunk(0, " this", None),
+			chunk(1, " this", None),
+			chunk(0, " is a test", None),
+			chunk(1, " is", None),
+			chunk(0, "", Some("length")),
+			chunk(1, "", Some("length")),
+			json!({
+				"id": "cmpl-123",
+				"object": "text_completion",
+				"created": 1589478378,
+				"model": "babbage-002",
+				"choices": [],
+				"usage": { "prompt_tokens": 5, "completion_tokens": 7, "total_tokens": 12 }
+			}),
+		]));
+		let body = CompletionsBody {
+			model: "babbage-002".to_string(),
+			prompt: Some(vec!["Say this is a test".to_string()]),
+			suffix: None,
+			max_tokens: Some(7),
+			temperature: Some(0_f32),
+			top_p: Some(0_f32),
+			n: Some(2),
+			stream: None,
+			stream_options: Some(StreamOptions { include_usage: true }),
+			logprobs: None,
+			echo: None,
+			stop: Some(vec!["\n".to_string()]),
+			presence_penalty: None,
+			frequency_penalty: None,
+			best_of: None,
+			logit_bias: None,
+			user: None,
+		};
+		let stream = openai.completion_stream(&body).unwrap();
+		let completion = Completion::from_chunks(stream).unwrap();
+		assert_eq!(completion.choices.len(), 2);
+		assert_eq!(completion.choices[0].text.as_deref(), Some(" this is a test"));
+		assert_eq!(completion.choices[1].text.as_deref(), Some(" this is"));
+		assert_eq!(completion.choices[1].finish_reason.as_deref(), Some("length"));
+		assert_eq!(completion.usage.total_tokens, Some(12));
+
+		let request = &transport.requests()[0];
+		assert_eq!(request.header("accept"), Some("text/event-stream"));
+		assert_eq!(request.json().unwrap()["stream"], true);
+		assert_eq!(request.json().unwrap()["stream_options"], json!({ "include_usage": true }));
+	}
+
+	#[test]
+	fn test_completion_from_chunks() {
+		let chunk = |index: u32, text: &str, finish_reason: Option<&str>| {
+			let logprobs =
+				json!({ "tokens": [text], "token_logprobs": [-0.5], "text_offset": [0] });
+			Ok(CompletionChunk {
+				id: Some("cmpl-1".to_string()),
+				object: Some("text_completion".to_string()),
+				created: Some(1),
+				model: Some("babbage-002".to_string()),
+				choices: vec![Choice {
+					text: Some(text.to_string()),
+					index,
+					logprobs: Some(logprobs),
+					finish_reason: finish_reason.map(|r| r.to_string()),
+					message: None,
+				}],
+				usage: None,
+			})
+		};
+		let usage = Ok(CompletionChunk {
+			id: Some("cmpl-1".to_string()),
+			object: Some("text_completion".to_string()),
+			created: Some(1),
+			model: Some("babbage-002".to_string()),
+			choices: vec![],
+			usage: Some(Usage {
+				prompt_tokens: Some(5),
+				completion_tokens: Some(6),
+				total_tokens: Some(11),
+			}),
+		});
+		let chunks = vec![
+			chunk(1, "Hello", None),
+			chunk(0, "This", None),
+			chunk(0, " is", None),
+			chunk(1, " world", Some("length")),
+			chunk(0, " a test", Some("stop")),
+			usage,
+		];
+		let completion = Completion::from_chunks(chunks).unwrap();
+		assert_eq!(completion.usage.total_tokens, Some(11));
+		assert_eq!(
+			completion.choices[0].logprobs,
+			Some(json!({
+				"tokens": ["This", " is", " a test"],
+				"token_logprobs": [-0.5, -0.5, -0.5],
+				"text_offset": [0, 0, 0]
+			}))
+		);
+		assert_eq!(completion.id.as_deref(), Some("cmpl-1"));
+		assert_eq!(completion.choices.len(), 2);
+		assert_eq!(completion.choices[0].text.as_deref(), Some("This is a test"));
+		assert_eq!(completion.choices[0].finish_reason.as_deref(), Some("stop"));
+		assert_eq!(completion.choices[1].text.as_deref(), Some("Hello world"));
+		assert_eq!(completion.choices[1].finish_reason.as_deref(), Some("length"));
 	}
 }
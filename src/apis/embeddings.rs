@@ -6,11 +6,14 @@
 
 use serde::{Deserialize, Serialize};
 
-use crate::requests::Requests;
+use crate::requests::{from_json, to_json, Requests};
 use crate::*;
 
 use super::{Usage, EMBEDDINGS_CREATE};
 
+#[cfg(feature = "async")]
+use {crate::async_requests::AsyncRequests, std::future::Future};
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct EmbeddingsBody {
 	/// ID of the model to use. You can use the List models API to see all of your available models,
@@ -46,23 +49,57 @@ pub trait EmbeddingsApi {
 
 impl EmbeddingsApi for OpenAI {
 	fn embeddings_create(&self, embeddings_body: &EmbeddingsBody) -> ApiResult<Embeddings> {
-		let request_body = serde_json::to_value(embeddings_body).unwrap();
+		let request_body = to_json(embeddings_body)?;
 		let res = self.post(EMBEDDINGS_CREATE, request_body)?;
-		let embeddings: Embeddings = serde_json::from_value(res.clone()).unwrap();
+		let embeddings: Embeddings = from_json(&res)?;
+		Ok(embeddings)
+	}
+}
+
+/// The async counterpart of [`EmbeddingsApi`].
+#[cfg(feature = "async")]
+pub trait AsyncEmbeddingsApi {
+	/// Creates an embedding vector representing the input text.
+	fn embeddings_create(
+		&self,
+		embeddings_body: &EmbeddingsBody,
+	) -> impl Future<Output = ApiResult<Embeddings>> + Send;
+}
+
+#[cfg(feature = "async")]
+impl AsyncEmbeddingsApi for AsyncOpenAI {
+	async fn embeddings_create(&self, embeddings_body: &EmbeddingsBody) -> ApiResult<Embeddings> {
+		let request_body = to_json(embeddings_body)?;
+		let res = self.post(EMBEDDINGS_CREATE, request_body).await?;
+		let embeddings: Embeddings = from_json(&res)?;
 		Ok(embeddings)
 	}
 }
 
 #[cfg(test)]
 mod tests {
+	use serde_json::json;
+
 	use crate::{
 		apis::embeddings::{EmbeddingsApi, EmbeddingsBody},
-		openai::new_test_openai,
+		openai::new_mock_openai,
+		MockResponse,
 	};
 
+	// A response with a shortened vector.
+	fn embeddings_response() -> serde_json::Value {
+		json!({
+			"object": "list",
+			"data": [{ "object": "embedding", "index": 0, "embedding": [0.0023064255, -0.009327292, 0.015797347] }],
+			"model": "text-embedding-ada-002",
+			"usage": { "prompt_tokens": 8, "total_tokens": 8 }
+		})
+	}
+
 	#[test]
 	fn test_embedding_create() {
-		let openai = new_test_openai();
+		let (openai, transport) = new_mock_openai();
+		transport.push(MockResponse::json(200, embeddings_response()));
 		let body = EmbeddingsBody {
 			model: "text-embedding-ada-002".to_string(),
 			input: vec!["The food was delicious and the waiter...".to_string()],
@@ -70,8 +107,35 @@ mod tests {
 		};
 		let rs = openai.embeddings_create(&body);
 		let embeddings = rs.unwrap().data;
-		let embedding = embeddings.as_ref().unwrap().get(0).unwrap();
+		let embedding = embeddings.as_ref().unwrap().first().unwrap();
 		let f = embedding.embedding.as_ref().unwrap();
-		assert!(!f.is_empty());
+		assert_eq!(f.len(), 3);
+
+		let request = &transport.requests()[0];
+		assert_eq!(request.url, "https://api.openai.com/v1/embeddings");
+		assert_eq!(
+			request.json().unwrap(),
+			json!({
+				"model": "text-embedding-ada-002",
+				"input": ["The food was delicious and the waiter..."]
+			})
+		);
+	}
+
+	#[cfg(feature = "async")]
+	#[tokio::test]
+	async fn test_async_embedding_create() {
+		use crate::{apis::embeddings::AsyncEmbeddingsApi, openai::new_mock_async_openai};
+
+		let (openai, transport) = new_mock_async_openai();
+		transport.push(MockResponse::json(200, embeddings_response()));
+		let body = EmbeddingsBody {
+			model: "text-embedding-ada-002".to_string(),
+			input: vec!["The food was delicious and the waiter...".to_string()],
+			user: None,
+		};
+		let rs = openai.embeddings_create(&body).await;
+		let embeddings = rs.unwrap().data.unwrap();
+		assert!(!embeddings[0].embedding.as_ref().unwrap().is_empty());
 	}
 }
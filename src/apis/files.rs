@@ -0,0 +1,334 @@
+// Files are used to upload documents that can be used with features like
+// fine-tuning and batches.
+// See: https://platform.openai.com/docs/api-reference/files
+
+//! Files API
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::mpart::Mpart as Multipart;
+use crate::requests::{from_json, Requests};
+use crate::*;
+
+use super::{encode_query, HasId, ListParams, Page, FILES, FILES_RETRIEVE};
+
+#[cfg(feature = "async")]
+use {
+	crate::async_requests::{AsyncRequests, FormData},
+	std::future::Future,
+};
+
+pub struct FileUploadBody {
+	/// The content of the file.
+	pub file: Box<dyn Read + Send>,
+	/// The name of the file, its extension tells the API how to read it, e.g. `batch.jsonl`.
+	pub filename: String,
+	/// The intended purpose of the file: assistants, batch, fine-tune, vision or user_data.
+	pub purpose: String,
+}
+
+impl FileUploadBody {
+	/// Uploads the file at `path`, keeping its name.
+	pub fn from_path(path: impl AsRef<Path>, purpose: &str) -> ApiResult<FileUploadBody> {
+		let path = path.as_ref();
+		let filename = match path.file_name() {
+			Some(name) => name.to_string_lossy().into_owned(),
+			None => return Err(Error::InvalidInput(format!("Not a file: {}", path.display()))),
+		};
+		Ok(FileUploadBody {
+			file: Box::new(File::open(path)?),
+			filename,
+			purpose: purpose.to_string(),
+		})
+	}
+
+	/// Uploads `content`, e.g. a JSONL file built in memory.
+	pub fn from_bytes(content: Vec<u8>, filename: &str, purpose: &str) -> FileUploadBody {
+		FileUploadBody {
+			file: Box::new(io::Cursor::new(content)),
+			filename: filename.to_string(),
+			purpose: purpose.to_string(),
+		}
+	}
+}
+
+impl fmt::Debug for FileUploadBody {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("FileUploadBody")
+			.field("filename", &self.filename)
+			.field("purpose", &self.purpose)
+			.finish_non_exhaustive()
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileObject {
+	/// The file identifier, which can be referenced in the API endpoints.
+	pub id: String,
+	pub object: Option<String>,
+	/// The size of the file, in bytes.
+	pub bytes: u64,
+	/// The Unix timestamp (in seconds) for when the file was created.
+	pub created_at: u64,
+	pub filename: String,
+	/// The intended purpose of the file.
+	pub purpose: String,
+	/// Deprecated, the current status of the file: uploaded, processed or error.
+	pub status: Option<String>,
+	/// Deprecated, the reason why a fine-tuning training file failed validation.
+	pub status_details: Option<String>,
+}
+
+impl HasId for FileObject {
+	fn id(&self) -> &str {
+		&self.id
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeletedFile {
+	pub id: String,
+	pub object: Option<String>,
+	pub deleted: bool,
+}
+
+pub trait FilesApi {
+	/// Uploads a file that can be used across various endpoints.
+	///
+	/// The whole file is loaded into memory, to be sent again on retries, unless the
+	/// [`RetryPolicy`] has a `max_attempts` of 1, in which case it is streamed.
+	fn files_upload(&self, upload_body: FileUploadBody) -> ApiResult<FileObject>;
+	/// Returns a page of the files, only those with the given purpose if any.
+	fn files_list(&self, purpose: Option<&str>, params: &ListParams)
+		-> ApiResult<Page<FileObject>>;
+	/// Returns information about a specific file.
+	fn files_retrieve(&self, file_id: &str) -> ApiResult<FileObject>;
+	/// Deletes a file.
+	fn files_delete(&self, file_id: &str) -> ApiResult<DeletedFile>;
+	/// Writes the contents of the file to `writer` as it is downloaded,
+	/// returning the number of bytes written.
+	fn files_content(&self, file_id: &str, writer: &mut dyn Write) -> ApiResult<u64>;
+}
+
+impl FilesApi for OpenAI {
+	fn files_upload(&self, upload_body: FileUploadBody) -> ApiResult<FileObject> {
+		let send_data = build_multipart(upload_body);
+		let res = self.post_multipart(FILES, send_data)?;
+		let file: FileObject = from_json(&res)?;
+		Ok(file)
+	}
+
+	fn files_list(
+		&self,
+		purpose: Option<&str>,
+		params: &ListParams,
+	) -> ApiResult<Page<FileObject>> {
+		let res = self.get(&list_url(purpose, params))?;
+		from_json(&res)
+	}
+
+	fn files_retrieve(&self, file_id: &str) -> ApiResult<FileObject> {
+		let res: Json = self.get(&(FILES_RETRIEVE.to_owned() + file_id))?;
+		let file: FileObject = from_json(&res)?;
+		Ok(file)
+	}
+
+	fn files_delete(&self, file_id: &str) -> ApiResult<DeletedFile> {
+		let res: Json = self.delete(&(FILES_RETRIEVE.to_owned() + file_id))?;
+		let deleted: DeletedFile = from_json(&res)?;
+		Ok(deleted)
+	}
+
+	fn files_content(&self, file_id: &str, writer: &mut dyn Write) -> ApiResult<u64> {
+		let mut reader = self.get_stream(&format!("{FILES_RETRIEVE}{file_id}/content"))?;
+		Ok(io::copy(&mut reader, writer)?)
+	}
+}
+
+fn build_multipart(upload_body: FileUploadBody) -> Multipart<'static, 'static> {
+	let mut send_data = Multipart::new();
+
+	send_data.add_text("purpose", upload_body.purpose);
+	send_data.add_stream("file", upload_body.file, Some(upload_body.filename), None);
+	send_data
+}
+
+fn list_url(purpose: Option<&str>, params: &ListParams) -> String {
+	match purpose {
+		Some(purpose) => params.url(&format!("{FILES}?purpose={}", encode_query(purpose))),
+		None => params.url(FILES),
+	}
+}
+
+/// The async counterpart of [`FilesApi`].
+#[cfg(feature = "async")]
+pub trait AsyncFilesApi {
+	/// Uploads a file that can be used across various endpoints.
+	///
+	/// The whole file is loaded into memory, whatever the [`RetryPolicy`].
+	fn files_upload(
+		&self,
+		upload_body: FileUploadBody,
+	) -> impl Future<Output = ApiResult<FileObject>> + Send;
+	/// Returns a page of the files, only those with the given purpose if any.
+	fn files_list(
+		&self,
+		purpose: Option<&str>,
+		params: &ListParams,
+	) -> impl Future<Output = ApiResult<Page<FileObject>>> + Send;
+	/// Returns information about a specific file.
+	fn files_retrieve(&self, file_id: &str) -> impl Future<Output = ApiResult<FileObject>> + Send;
+	/// Deletes a file.
+	fn files_delete(&self, file_id: &str) -> impl Future<Output = ApiResult<DeletedFile>> + Send;
+	/// Writes the contents of the file to `writer` as it is downloaded,
+	/// returning the number of bytes written.
+	fn files_content(
+		&self,
+		file_id: &str,
+		writer: &mut (dyn Write + Send),
+	) -> impl Future<Output = ApiResult<u64>> + Send;
+}
+
+#[cfg(feature = "async")]
+impl AsyncFilesApi for AsyncOpenAI {
+	async fn files_upload(&self, upload_body: FileUploadBody) -> ApiResult<FileObject> {
+		let form_data = FormData::new(build_multipart(upload_body))?;
+		let res = self.post_multipart(FILES, form_data).await?;
+		let file: FileObject = from_json(&res)?;
+		Ok(file)
+	}
+
+	async fn files_list(
+		&self,
+		purpose: Option<&str>,
+		params: &ListParams,
+	) -> ApiResult<Page<FileObject>> {
+		let res = self.get(&list_url(purpose, params)).await?;
+		from_json(&res)
+	}
+
+	async fn files_retrieve(&self, file_id: &str) -> ApiResult<FileObject> {
+		let res: Json = self.get(&(FILES_RETRIEVE.to_owned() + file_id)).await?;
+		let file: FileObject = from_json(&res)?;
+		Ok(file)
+	}
+
+	async fn files_delete(&self, file_id: &str) -> ApiResult<DeletedFile> {
+		let res: Json = self.delete(&(FILES_RETRIEVE.to_owned() + file_id)).await?;
+		let deleted: DeletedFile = from_json(&res)?;
+		Ok(deleted)
+	}
+
+	async fn files_content(
+		&self,
+		file_id: &str,
+		writer: &mut (dyn Write + Send),
+	) -> ApiResult<u64> {
+		let mut response = self.get_stream(&format!("{FILES_RETRIEVE}{file_id}/content")).await?;
+		let mut written = 0;
+		while let Some(chunk) = response.chunk().await? {
+			writer.write_all(&chunk)?;
+			written += chunk.len() as u64;
+		}
+		Ok(written)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use serde_json::json;
+
+	use crate::{
+		apis::files::{list_url, FileUploadBody, FilesApi},
+		apis::ListParams,
+		openai::new_mock_openai,
+		MockResponse,
+	};
+
+	fn file_object() -> serde_json::Value {
+		json!({
+			"id": "file-abc123",
+			"object": "file",
+			"bytes": 120000,
+			"created_at": 1677610602,
+			"filename": "batch.jsonl",
+			"purpose": "batch"
+		})
+	}
+
+	#[test]
+	fn test_files_upload() {
+		let (openai, transport) = new_mock_openai();
+		transport.push(MockResponse::json(200, file_object()));
+		let body = FileUploadBody::from_bytes(b"{\"a\": 1}\n".to_vec(), "batch.jsonl", "batch");
+		let file = openai.files_upload(body).unwrap();
+		assert_eq!(file.id, "file-abc123");
+		assert_eq!(file.bytes, 120000);
+
+		let request = &transport.requests()[0];
+		assert_eq!(request.url, "https://api.openai.com/v1/files");
+		assert!(request.header("content-type").unwrap().starts_with("multipart/form-data"));
+		let body = String::from_utf8_lossy(request.body.as_ref().unwrap()).into_owned();
+		assert!(body.contains("name=\"purpose\"\r\n\r\nbatch\r\n"));
+		assert!(body.contains("filename=\"batch.jsonl\""));
+		assert!(body.contains("{\"a\": 1}\n"));
+	}
+
+	#[test]
+	fn test_files_list_retrieve_delete() {
+		let (openai, transport) = new_mock_openai();
+		transport
+			.push(MockResponse::json(
+				200,
+				json!({ "object": "list", "data": [file_object()], "has_more": true }),
+			))
+			.push(MockResponse::json(200, file_object()))
+			.push(MockResponse::json(
+				200,
+				json!({ "id": "file-abc123", "object": "file", "deleted": true }),
+			));
+		let params = ListParams { limit: Some(1), ..Default::default() };
+		let page = openai.files_list(Some("batch"), &params).unwrap();
+		assert_eq!(page.data[0].filename, "batch.jsonl");
+		let next = params.next(&page).unwrap();
+		assert_eq!(next.after.as_deref(), Some("file-abc123"));
+		assert_eq!(openai.files_retrieve("file-abc123").unwrap().purpose, "batch");
+		assert!(openai.files_delete("file-abc123").unwrap().deleted);
+
+		let requests = transport.requests();
+		assert_eq!(requests[0].url, "https://api.openai.com/v1/files?purpose=batch&limit=1");
+		assert_eq!(requests[1].url, "https://api.openai.com/v1/files/file-abc123");
+		assert_eq!(requests[2].method, "DELETE");
+		assert_eq!(requests[2].url, "https://api.openai.com/v1/files/file-abc123");
+	}
+
+	#[test]
+	fn test_files_list_url() {
+		let params = ListParams { after: Some("file-abc123".to_string()), limit: None };
+		assert_eq!(list_url(None, &ListParams::default()), "files");
+		assert_eq!(
+			list_url(Some("fine-tune"), &params),
+			"files?purpose=fine-tune&after=file-abc123"
+		);
+		assert_eq!(list_url(Some("a&b"), &ListParams::default()), "files?purpose=a%26b");
+	}
+
+	#[test]
+	fn test_files_content() {
+		let (openai, transport) = new_mock_openai();
+		transport.push(MockResponse::new(200, "{\"custom_id\": \"1\"}\n"));
+		let mut content = Vec::new();
+		let written = openai.files_content("file-abc123", &mut content).unwrap();
+		assert_eq!(written, 19);
+		assert_eq!(content, b"{\"custom_id\": \"1\"}\n");
+		assert_eq!(
+			transport.requests()[0].url,
+			"https://api.openai.com/v1/files/file-abc123/content"
+		);
+	}
+}
@@ -0,0 +1,580 @@
+// Manage fine-tuning jobs to tailor a model to your specific training data.
+// See: https://platform.openai.com/docs/api-reference/fine-tuning
+
+//! Fine-tuning API
+
+use std::ops::ControlFlow;
+use std::thread;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::requests::{from_json, to_json, Requests};
+use crate::*;
+
+use super::{HasId, ListParams, Page, FINE_TUNING_JOBS, FINE_TUNING_JOBS_RETRIEVE};
+
+#[cfg(feature = "async")]
+use {crate::async_requests::AsyncRequests, std::future::Future};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FineTuningJobBody {
+	/// The name of the model to fine-tune.
+	pub model: String,
+	/// The ID of an uploaded file that contains training data, with the purpose `fine-tune`.
+	pub training_file: String,
+	/// The ID of an uploaded file that contains validation data.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub validation_file: Option<String>,
+	/// The hyperparameters used for the fine-tuning job.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub hyperparameters: Option<Hyperparameters>,
+	/// A string of up to 64 characters that will be added to your fine-tuned model name.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub suffix: Option<String>,
+	/// The seed controls the reproducibility of the job.
+	/// Defaults to a random seed
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub seed: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Hyperparameters {
+	/// Number of examples in each batch.
+	/// Defaults to auto
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub batch_size: Option<u32>,
+	/// Scaling factor for the learning rate.
+	/// Defaults to auto
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub learning_rate_multiplier: Option<f64>,
+	/// The number of epochs to train the model for.
+	/// Defaults to auto
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub n_epochs: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FineTuningJob {
+	pub id: String,
+	pub object: Option<String>,
+	/// The Unix timestamp (in seconds) for when the job was created.
+	pub created_at: u64,
+	/// The Unix timestamp (in seconds) for when the job finished, if it did.
+	pub finished_at: Option<u64>,
+	/// The base model that is being fine-tuned.
+	pub model: String,
+	/// The name of the fine-tuned model, once the job succeeded.
+	pub fine_tuned_model: Option<String>,
+	pub organization_id: Option<String>,
+	pub status: FineTuningStatus,
+	/// The hyperparameters, either numbers or `"auto"` until the job resolves them.
+	pub hyperparameters: Option<Json>,
+	pub training_file: String,
+	pub validation_file: Option<String>,
+	/// The IDs of the result files, which can be downloaded with the Files API.
+	#[serde(default)]
+	pub result_files: Vec<String>,
+	/// The total number of billable tokens processed, once the job finished.
+	pub trained_tokens: Option<u64>,
+	/// Why the job failed, if it did.
+	pub error: Option<FineTuningError>,
+	pub seed: Option<u64>,
+	/// The Unix timestamp (in seconds) for when the job is estimated to finish.
+	pub estimated_finish: Option<u64>,
+}
+
+impl HasId for FineTuningJob {
+	fn id(&self) -> &str {
+		&self.id
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FineTuningStatus {
+	ValidatingFiles,
+	Queued,
+	Running,
+	Succeeded,
+	Failed,
+	Cancelled,
+	/// A status added to the API after this version.
+	#[serde(other)]
+	Unknown,
+}
+
+impl FineTuningStatus {
+	/// Whether the job is over, successfully or not.
+	pub fn is_finished(self) -> bool {
+		matches!(self, Self::Succeeded | Self::Failed | Self::Cancelled)
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FineTuningError {
+	pub code: Option<String>,
+	pub message: Option<String>,
+	/// The parameter that was invalid, e.g. `training_file`.
+	pub param: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FineTuningEvent {
+	pub id: String,
+	pub object: Option<String>,
+	pub created_at: u64,
+	/// info, warn or error.
+	pub level: String,
+	pub message: String,
+	/// message or metrics.
+	#[serde(rename = "type")]
+	pub event_type: Option<String>,
+	/// The metrics of `metrics` events, e.g. the step and train loss.
+	pub data: Option<Json>,
+}
+
+impl HasId for FineTuningEvent {
+	fn id(&self) -> &str {
+		&self.id
+	}
+}
+
+/// A model checkpoint, saved at the end of a training epoch.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FineTuningCheckpoint {
+	pub id: String,
+	pub object: Option<String>,
+	pub created_at: u64,
+	/// The name of the model of the checkpoint, which can be used like a fine-tuned model.
+	pub fine_tuned_model_checkpoint: String,
+	pub step_number: u64,
+	/// The metrics at the step of the checkpoint, e.g. `train_loss` and `valid_loss`.
+	pub metrics: Json,
+	pub fine_tuning_job_id: String,
+}
+
+impl HasId for FineTuningCheckpoint {
+	fn id(&self) -> &str {
+		&self.id
+	}
+}
+
+pub trait FineTuningApi {
+	/// Creates a fine-tuning job which begins the process of creating a new model from a given dataset.
+	fn fine_tuning_create(&self, job_body: &FineTuningJobBody) -> ApiResult<FineTuningJob>;
+	/// Lists your organization's fine-tuning jobs, most recent first.
+	fn fine_tuning_list(&self, params: &ListParams) -> ApiResult<Page<FineTuningJob>>;
+	/// Gets info about a fine-tuning job.
+	fn fine_tuning_retrieve(&self, job_id: &str) -> ApiResult<FineTuningJob>;
+	/// Immediately cancels a fine-tuning job.
+	fn fine_tuning_cancel(&self, job_id: &str) -> ApiResult<FineTuningJob>;
+	/// Gets status updates for a fine-tuning job, most recent first.
+	fn fine_tuning_events(
+		&self,
+		job_id: &str,
+		params: &ListParams,
+	) -> ApiResult<Page<FineTuningEvent>>;
+	/// Lists the checkpoints of a fine-tuning job.
+	fn fine_tuning_checkpoints(
+		&self,
+		job_id: &str,
+		params: &ListParams,
+	) -> ApiResult<Page<FineTuningCheckpoint>>;
+	/// Retrieves the job every `poll_interval` until it is finished, calling `on_poll`
+	/// with each retrieved job, and returns the finished job.
+	///
+	/// A failed or cancelled job is returned as `Ok`, check its `status`.
+	/// `on_poll` returning `ControlFlow::Break` stops the wait and returns the job as it is,
+	/// e.g. on a timeout or an [`FineTuningStatus::Unknown`] status.
+	fn fine_tuning_wait_for_completion(
+		&self,
+		job_id: &str,
+		poll_interval: Duration,
+		on_poll: &mut dyn FnMut(&FineTuningJob) -> ControlFlow<()>,
+	) -> ApiResult<FineTuningJob>;
+}
+
+impl FineTuningApi for OpenAI {
+	fn fine_tuning_create(&self, job_body: &FineTuningJobBody) -> ApiResult<FineTuningJob> {
+		let request_body = to_json(job_body)?;
+		let res = self.post(FINE_TUNING_JOBS, request_body)?;
+		let job: FineTuningJob = from_json(&res)?;
+		Ok(job)
+	}
+
+	fn fine_tuning_list(&self, params: &ListParams) -> ApiResult<Page<FineTuningJob>> {
+		let res = self.get(&params.url(FINE_TUNING_JOBS))?;
+		from_json(&res)
+	}
+
+	fn fine_tuning_retrieve(&self, job_id: &str) -> ApiResult<FineTuningJob> {
+		let res = self.get(&(FINE_TUNING_JOBS_RETRIEVE.to_owned() + job_id))?;
+		let job: FineTuningJob = from_json(&res)?;
+		Ok(job)
+	}
+
+	fn fine_tuning_cancel(&self, job_id: &str) -> ApiResult<FineTuningJob> {
+		let sub_url = format!("{FINE_TUNING_JOBS_RETRIEVE}{job_id}/cancel");
+		let res = self.post(&sub_url, Json::Object(Default::default()))?;
+		let job: FineTuningJob = from_json(&res)?;
+		Ok(job)
+	}
+
+	fn fine_tuning_events(
+		&self,
+		job_id: &str,
+		params: &ListParams,
+	) -> ApiResult<Page<FineTuningEvent>> {
+		let res = self.get(&params.url(&format!("{FINE_TUNING_JOBS_RETRIEVE}{job_id}/events")))?;
+		from_json(&res)
+	}
+
+	fn fine_tuning_checkpoints(
+		&self,
+		job_id: &str,
+		params: &ListParams,
+	) -> ApiResult<Page<FineTuningCheckpoint>> {
+		let res =
+			self.get(&params.url(&format!("{FINE_TUNING_JOBS_RETRIEVE}{job_id}/checkpoints")))?;
+		from_json(&res)
+	}
+
+	fn fine_tuning_wait_for_completion(
+		&self,
+		job_id: &str,
+		poll_interval: Duration,
+		on_poll: &mut dyn FnMut(&FineTuningJob) -> ControlFlow<()>,
+	) -> ApiResult<FineTuningJob> {
+		loop {
+			let job = self.fine_tuning_retrieve(job_id)?;
+			if on_poll(&job).is_break() || job.status.is_finished() {
+				return Ok(job);
+			}
+			thread::sleep(poll_interval);
+		}
+	}
+}
+
+/// The async counterpart of [`FineTuningApi`].
+#[cfg(feature = "async")]
+pub trait AsyncFineTuningApi {
+	/// Creates a fine-tuning job which begins the process of creating a new model from a given dataset.
+	fn fine_tuning_create(
+		&self,
+		job_body: &FineTuningJobBody,
+	) -> impl Future<Output = ApiResult<FineTuningJob>> + Send;
+	/// Lists your organization's fine-tuning jobs, most recent first.
+	fn fine_tuning_list(
+		&self,
+		params: &ListParams,
+	) -> impl Future<Output = ApiResult<Page<FineTuningJob>>> + Send;
+	/// Gets info about a fine-tuning job.
+	fn fine_tuning_retrieve(
+		&self,
+		job_id: &str,
+	) -> impl Future<Output = ApiResult<FineTuningJob>> + Send;
+	/// Immediately cancels a fine-tuning job.
+	fn fine_tuning_cancel(
+		&self,
+		job_id: &str,
+	) -> impl Future<Output = ApiResult<FineTuningJob>> + Send;
+	/// Gets status updates for a fine-tuning job, most recent first.
+	fn fine_tuning_events(
+		&self,
+		job_id: &str,
+		params: &ListParams,
+	) -> impl Future<Output = ApiResult<Page<FineTuningEvent>>> + Send;
+	/// Lists the checkpoints of a fine-tuning job.
+	fn fine_tuning_checkpoints(
+		&self,
+		job_id: &str,
+		params: &ListParams,
+	) -> impl Future<Output = ApiResult<Page<FineTuningCheckpoint>>> + Send;
+	/// Retrieves the job every `poll_interval` until it is finished, calling `on_poll`
+	/// with each retrieved job, and returns the finished job.
+	///
+	/// A failed or cancelled job is returned as `Ok`, check its `status`.
+	/// `on_poll` returning `ControlFlow::Break` stops the wait and returns the job as it is,
+	/// e.g. on a timeout or an [`FineTuningStatus::Unknown`] status.
+	fn fine_tuning_wait_for_completion(
+		&self,
+		job_id: &str,
+		poll_interval: Duration,
+		on_poll: &mut (dyn FnMut(&FineTuningJob) -> ControlFlow<()> + Send),
+	) -> impl Future<Output = ApiResult<FineTuningJob>> + Send;
+}
+
+#[cfg(feature = "async")]
+impl AsyncFineTuningApi for AsyncOpenAI {
+	async fn fine_tuning_create(&self, job_body: &FineTuningJobBody) -> ApiResult<FineTuningJob> {
+		let request_body = to_json(job_body)?;
+		let res = self.post(FINE_TUNING_JOBS, request_body).await?;
+		let job: FineTuningJob = from_json(&res)?;
+		Ok(job)
+	}
+
+	async fn fine_tuning_list(&self, params: &ListParams) -> ApiResult<Page<FineTuningJob>> {
+		let res = self.get(&params.url(FINE_TUNING_JOBS)).await?;
+		from_json(&res)
+	}
+
+	async fn fine_tuning_retrieve(&self, job_id: &str) -> ApiResult<FineTuningJob> {
+		let res = self.get(&(FINE_TUNING_JOBS_RETRIEVE.to_owned() + job_id)).await?;
+		let job: FineTuningJob = from_json(&res)?;
+		Ok(job)
+	}
+
+	async fn fine_tuning_cancel(&self, job_id: &str) -> ApiResult<FineTuningJob> {
+		let sub_url = format!("{FINE_TUNING_JOBS_RETRIEVE}{job_id}/cancel");
+		let res = self.post(&sub_url, Json::Object(Default::default())).await?;
+		let job: FineTuningJob = from_json(&res)?;
+		Ok(job)
+	}
+
+	async fn fine_tuning_events(
+		&self,
+		job_id: &str,
+		params: &ListParams,
+	) -> ApiResult<Page<FineTuningEvent>> {
+		let sub_url = params.url(&format!("{FINE_TUNING_JOBS_RETRIEVE}{job_id}/events"));
+		let res = self.get(&sub_url).await?;
+		from_json(&res)
+	}
+
+	async fn fine_tuning_checkpoints(
+		&self,
+		job_id: &str,
+		params: &ListParams,
+	) -> ApiResult<Page<FineTuningCheckpoint>> {
+		let sub_url = params.url(&format!("{FINE_TUNING_JOBS_RETRIEVE}{job_id}/checkpoints"));
+		let res = self.get(&sub_url).await?;
+		from_json(&res)
+	}
+	async fn fine_tuning_wait_for_completion(
+		&self,
+		job_id: &str,
+		poll_interval: Duration,
+		on_poll: &mut (dyn FnMut(&FineTuningJob) -> ControlFlow<()> + Send),
+	) -> ApiResult<FineTuningJob> {
+		loop {
+			let job = self.fine_tuning_retrieve(job_id).await?;
+			if on_poll(&job).is_break() || job.status.is_finished() {
+				return Ok(job);
+			}
+			tokio::time::sleep(poll_interval).await;
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{ops::ControlFlow, time::Duration};
+
+	use serde_json::json;
+
+	use crate::{
+		apis::fine_tuning::{FineTuningApi, FineTuningJobBody, FineTuningStatus, Hyperparameters},
+		openai::new_mock_openai,
+		ListParams, MockResponse,
+	};
+
+	fn job(status: &str) -> serde_json::Value {
+		json!({
+			"object": "fine_tuning.job",
+			"id": "ftjob-abc123",
+			"model": "gpt-4o-mini-2024-07-18",
+			"created_at": 1721764800,
+			"finished_at": null,
+			"fine_tuned_model": null,
+			"organization_id": "org-123",
+			"result_files": [],
+			"status": status,
+			"validation_file": null,
+			"training_file": "file-abc123",
+			"hyperparameters": { "n_epochs": "auto" },
+			"seed": 42
+		})
+	}
+
+	#[test]
+	fn test_fine_tuning_create() {
+		let (openai, transport) = new_mock_openai();
+		transport.push(MockResponse::json(200, job("queued")));
+		let body = FineTuningJobBody {
+			model: "gpt-4o-mini-2024-07-18".to_string(),
+			training_file: "file-abc123".to_string(),
+			hyperparameters: Some(Hyperparameters { n_epochs: Some(3), ..Default::default() }),
+			suffix: Some("custom".to_string()),
+			..Default::default()
+		};
+		let job = openai.fine_tuning_create(&body).unwrap();
+		assert_eq!(job.status, FineTuningStatus::Queued);
+
+		let request = &transport.requests()[0];
+		assert_eq!(request.url, "https://api.openai.com/v1/fine_tuning/jobs");
+		assert_eq!(
+			request.json().unwrap(),
+			json!({
+				"model": "gpt-4o-mini-2024-07-18",
+				"training_file": "file-abc123",
+				"hyperparameters": { "n_epochs": 3 },
+				"suffix": "custom"
+			})
+		);
+	}
+
+	#[test]
+	fn test_fine_tuning_list_pages() {
+		let (openai, transport) = new_mock_openai();
+		transport
+			.push(MockResponse::json(
+				200,
+				json!({ "object": "list", "data": [job("running")], "has_more": true }),
+			))
+			.push(MockResponse::json(
+				200,
+				json!({ "object": "list", "data": [], "has_more": false }),
+			));
+		let params = ListParams { limit: Some(1), ..Default::default() };
+		let page = openai.fine_tuning_list(&params).unwrap();
+		let params = params.next(&page).unwrap();
+		let page = openai.fine_tuning_list(&params).unwrap();
+		assert!(params.next(&page).is_none());
+
+		let requests = transport.requests();
+		assert_eq!(requests[0].url, "https://api.openai.com/v1/fine_tuning/jobs?limit=1");
+		assert_eq!(
+			requests[1].url,
+			"https://api.openai.com/v1/fine_tuning/jobs?after=ftjob-abc123&limit=1"
+		);
+	}
+
+	#[test]
+	fn test_fine_tuning_events_checkpoints_cancel() {
+		let (openai, transport) = new_mock_openai();
+		transport
+			.push(MockResponse::json(
+				200,
+				json!({
+					"object": "list",
+					"data": [{
+						"object": "fine_tuning.job.event",
+						"id": "ft-event-1",
+						"created_at": 1721764800,
+						"level": "info",
+						"message": "Fine tuning job successfully completed",
+						"type": "message"
+					}],
+					"has_more": false
+				}),
+			))
+			.push(MockResponse::json(
+				200,
+				json!({
+					"object": "list",
+					"data": [{
+						"object": "fine_tuning.job.checkpoint",
+						"id": "ftckpt_1",
+						"created_at": 1721764867,
+						"fine_tuned_model_checkpoint": "ft:gpt-4o-mini:org:custom:ckpt-step-1000",
+						"metrics": { "step": 1000, "train_loss": 0.1 },
+						"fine_tuning_job_id": "ftjob-abc123",
+						"step_number": 1000
+					}],
+					"has_more": false
+				}),
+			))
+			.push(MockResponse::json(200, job("cancelled")));
+		let events = openai.fine_tuning_events("ftjob-abc123", &ListParams::default()).unwrap();
+		assert_eq!(events.data[0].level, "info");
+		let checkpoints =
+			openai.fine_tuning_checkpoints("ftjob-abc123", &ListParams::default()).unwrap();
+		assert_eq!(checkpoints.data[0].step_number, 1000);
+		let job = openai.fine_tuning_cancel("ftjob-abc123").unwrap();
+		assert_eq!(job.status, FineTuningStatus::Cancelled);
+
+		let requests = transport.requests();
+		assert_eq!(
+			requests[0].url,
+			"https://api.openai.com/v1/fine_tuning/jobs/ftjob-abc123/events"
+		);
+		assert_eq!(
+			requests[1].url,
+			"https://api.openai.com/v1/fine_tuning/jobs/ftjob-abc123/checkpoints"
+		);
+		assert_eq!(requests[2].method, "POST");
+		assert_eq!(
+			requests[2].url,
+			"https://api.openai.com/v1/fine_tuning/jobs/ftjob-abc123/cancel"
+		);
+	}
+
+	#[test]
+	fn test_fine_tuning_wait_for_completion() {
+		let (openai, transport) = new_mock_openai();
+		transport
+			.push(MockResponse::json(200, job("validating_files")))
+			.push(MockResponse::json(200, job("running")))
+			.push(MockResponse::json(200, job("succeeded")));
+		let mut statuses = Vec::new();
+		let job = openai
+			.fine_tuning_wait_for_completion("ftjob-abc123", Duration::ZERO, &mut |job| {
+				statuses.push(job.status);
+				ControlFlow::Continue(())
+			})
+			.unwrap();
+		assert_eq!(job.status, FineTuningStatus::Succeeded);
+		assert_eq!(
+			statuses,
+			[
+				FineTuningStatus::ValidatingFiles,
+				FineTuningStatus::Running,
+				FineTuningStatus::Succeeded
+			]
+		);
+	}
+
+	#[test]
+	fn test_fine_tuning_wait_for_completion_break() {
+		let (openai, transport) = new_mock_openai();
+		transport
+			.push(MockResponse::json(200, job("paused")))
+			.push(MockResponse::json(200, job("paused")));
+		let mut polls = 0;
+		let job = openai
+			.fine_tuning_wait_for_completion("ftjob-abc123", Duration::ZERO, &mut |_| {
+				polls += 1;
+				if polls == 2 {
+					ControlFlow::Break(())
+				} else {
+					ControlFlow::Continue(())
+				}
+			})
+			.unwrap();
+		assert_eq!(job.status, FineTuningStatus::Unknown);
+		assert_eq!(transport.requests().len(), 2);
+	}
+
+	#[cfg(feature = "async")]
+	#[tokio::test]
+	async fn test_async_fine_tuning_wait_for_completion() {
+		use crate::{apis::fine_tuning::AsyncFineTuningApi, openai::new_mock_async_openai};
+
+		let (openai, transport) = new_mock_async_openai();
+		transport
+			.push(MockResponse::json(200, job("running")))
+			.push(MockResponse::json(200, job("failed")));
+		let mut polls = 0;
+		let job = openai
+			.fine_tuning_wait_for_completion("ftjob-abc123", Duration::ZERO, &mut |_| {
+				polls += 1;
+				ControlFlow::Continue(())
+			})
+			.await
+			.unwrap();
+		assert_eq!(job.status, FineTuningStatus::Failed);
+		assert_eq!(polls, 2);
+		assert_eq!(transport.requests().len(), 2);
+	}
+}
@@ -5,42 +5,391 @@
 
 use super::{IMAGES_CREATE, IMAGES_EDIT, IMAGES_VARIATIONS};
 use crate::mpart::Mpart as Multipart;
-use crate::requests::Requests;
+use crate::png::{self, PngInfo};
+use crate::requests::{from_json, to_json, Requests};
 use crate::*;
+use mime::Mime;
 use serde::{Deserialize, Serialize};
-use std::{fs::File, str};
+use std::{
+	fmt,
+	fs::File,
+	io::{self, Cursor, Read},
+	path::Path,
+	str,
+};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[cfg(feature = "async")]
+use {
+	crate::async_requests::{AsyncRequests, FormData},
+	std::future::Future,
+};
+
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct ImagesBody {
 	/// A text description of the desired image(s). The maximum length is 1000 characters.
 	pub prompt: String,
+	/// The model to use for image generation: dall-e-2, dall-e-3 or gpt-image-1.
+	/// Defaults to dall-e-2
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub model: Option<String>,
 	/// The number of images to generate. Must be between 1 and 10.
 	/// Defaults to 1
 	#[serde(skip_serializing_if = "Option::is_none")]
 	pub n: Option<i32>,
-	/// The size of the generated images. Must be one of 256x256, 512x512, or 1024x1024.
+	/// The size of the generated images. Must be one of 256x256, 512x512, or 1024x1024 for dall-e-2,
+	/// 1024x1024, 1792x1024, or 1024x1792 for dall-e-3,
+	/// and 1024x1024, 1536x1024, 1024x1536 or auto for gpt-image-1.
 	/// Defaults to 1024x1024
 	#[serde(skip_serializing_if = "Option::is_none")]
 	pub size: Option<String>,
 	/// The format in which the generated images are returned. Must be one of url or b64_json.
+	/// Not supported by gpt-image-1, which always returns b64_json.
 	/// Defaults to url
 	#[serde(skip_serializing_if = "Option::is_none")]
 	pub response_format: Option<String>,
+	/// The quality of the image: standard or hd for dall-e-3, low, medium or high for gpt-image-1.
+	/// Defaults to auto
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub quality: Option<String>,
+	/// The style of the generated images, vivid or natural. Only supported by dall-e-3.
+	/// Defaults to vivid
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub style: Option<String>,
+	/// The background of the generated images, transparent, opaque or auto.
+	/// Only supported by gpt-image-1, a transparent background requires the png or webp format.
+	/// Defaults to auto
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub background: Option<String>,
+	/// The format of the generated images, png, jpeg or webp. Only supported by gpt-image-1.
+	/// Defaults to png
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub output_format: Option<String>,
+	/// The compression level, from 0 to 100%, of the jpeg or webp generated images.
+	/// Only supported by gpt-image-1.
+	/// Defaults to 100
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub output_compression: Option<u8>,
 	/// A unique identifier representing your end-user, which can help OpenAI to monitor and detect abuse.
 	#[serde(skip_serializing_if = "Option::is_none")]
 	pub user: Option<String>,
 }
 
+/// An image to upload, whose filename and MIME type tell the API its format.
+pub struct ImageInput {
+	reader: Box<dyn Read + Send>,
+	filename: String,
+	mime: Mime,
+	/// The size in bytes, known upfront for files and bytes.
+	len: Option<u64>,
+}
+
+impl ImageInput {
+	/// The image file at `path`, its MIME type derived from the extension.
+	pub fn from_path<P: AsRef<Path>>(path: P) -> ApiResult<ImageInput> {
+		let path = path.as_ref();
+		let filename = match path.file_name() {
+			Some(name) => name.to_string_lossy().into_owned(),
+			None => return Err(Error::InvalidInput(format!("Not a file: {}", path.display()))),
+		};
+		Ok(ImageInput::from_reader(File::open(path)?, &filename))
+	}
+
+	/// An opened image file, `filename` being e.g. `otter.png`.
+	pub fn from_file(file: File, filename: &str) -> ImageInput {
+		let len = file.metadata().ok().filter(|m| m.is_file()).map(|m| m.len());
+		ImageInput { len, ..ImageInput::from_reader(file, filename) }
+	}
+
+	/// An in-memory image, `filename` being e.g. `otter.jpg`.
+	pub fn from_bytes(bytes: Vec<u8>, filename: &str) -> ImageInput {
+		let len = Some(bytes.len() as u64);
+		ImageInput { len, ..ImageInput::from_reader(Cursor::new(bytes), filename) }
+	}
+
+	/// An image read from `reader`, `filename` being e.g. `otter.webp`.
+	pub fn from_reader<R: Read + Send + 'static>(reader: R, filename: &str) -> ImageInput {
+		ImageInput {
+			reader: Box::new(reader),
+			filename: filename.to_string(),
+			mime: image_mime(filename).unwrap_or(mime::APPLICATION_OCTET_STREAM),
+			len: None,
+		}
+	}
+
+	/// Overrides the MIME type derived from the filename.
+	pub fn with_mime(self, mime: Mime) -> ImageInput {
+		ImageInput { mime, ..self }
+	}
+
+	pub fn filename(&self) -> &str {
+		&self.filename
+	}
+
+	pub fn mime(&self) -> &Mime {
+		&self.mime
+	}
+
+	/// Reads the whole image if it is at most `limit` bytes, to be put back with [`Self::put_back`].
+	///
+	/// Files and bytes larger than `limit` are rejected without reading them.
+	fn read_up_to(&mut self, limit: usize) -> ApiResult<Vec<u8>> {
+		let too_large = format!("larger than the {} MB limit", limit / MB);
+		if self.len.is_some_and(|len| len > limit as u64) {
+			return Err(self.invalid(too_large));
+		}
+		let mut bytes = Vec::new();
+		(&mut self.reader).take(limit as u64 + 1).read_to_end(&mut bytes)?;
+		if bytes.len() > limit {
+			self.put_back(bytes);
+			return Err(self.invalid(too_large));
+		}
+		self.len = Some(bytes.len() as u64);
+		Ok(bytes)
+	}
+
+	/// Puts the bytes read back in front of the rest of the image, to upload it afterwards.
+	fn put_back(&mut self, bytes: Vec<u8>) {
+		let rest = std::mem::replace(&mut self.reader, Box::new(io::empty()));
+		self.reader = Box::new(Cursor::new(bytes).chain(rest));
+	}
+
+	/// Checks the image is a png of at most `max_bytes`, returning its header.
+	fn check_png(&mut self, max_bytes: usize) -> ApiResult<PngInfo> {
+		let bytes = self.read_up_to(max_bytes)?;
+		let info = png::parse(&bytes);
+		self.put_back(bytes);
+		info.map_err(|err| self.invalid(err))
+	}
+
+	/// Checks the image is a png, jpeg or webp of at most `max_bytes`,
+	/// returning its header if it is a png.
+	fn check_image(&mut self, max_bytes: usize) -> ApiResult<Option<PngInfo>> {
+		let bytes = self.read_up_to(max_bytes)?;
+		let is_jpeg = bytes.starts_with(b"\xff\xd8\xff");
+		let is_webp = bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(b"WEBP");
+		let is_png = bytes.starts_with(b"\x89PNG");
+		let info = if is_jpeg || is_webp { Ok(None) } else { png::parse(&bytes).map(Some) };
+		self.put_back(bytes);
+		match info {
+			Ok(info) => Ok(info),
+			Err(err) if is_png => Err(self.invalid(err)),
+			Err(_) => Err(self.invalid("not a png, jpeg or webp image")),
+		}
+	}
+
+	fn invalid(&self, message: impl fmt::Display) -> Error {
+		Error::InvalidInput(format!("{}: {}", self.filename, message))
+	}
+
+	fn add_to(self, send_data: &mut Multipart<'static, 'static>, name: &'static str) {
+		let ImageInput { reader, filename, mime, .. } = self;
+		send_data.add_stream(name, reader, Some(filename), Some(mime));
+	}
+}
+
+impl fmt::Debug for ImageInput {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("ImageInput")
+			.field("filename", &self.filename)
+			.field("mime", &self.mime)
+			.finish_non_exhaustive()
+	}
+}
+
+/// The MIME type of an image file, from the extension of its name.
+pub(crate) fn image_mime(filename: &str) -> Option<Mime> {
+	let extension = Path::new(filename).extension().and_then(|e| e.to_str()).unwrap_or_default();
+	let mime = match extension.to_lowercase().as_str() {
+		"png" => mime::IMAGE_PNG,
+		"jpg" | "jpeg" => mime::IMAGE_JPEG,
+		"gif" => mime::IMAGE_GIF,
+		"webp" => "image/webp".parse().unwrap(),
+		_ => return None,
+	};
+	Some(mime)
+}
+
 #[derive(Debug)]
-pub struct ImagesEditBody {
-	/// The image to edit. Must be a valid PNG file, less than 4MB, and square.
-	/// If mask is not provided, image must have transparency, which will be used as the mask.
-	pub image: File,
+pub struct ImageEditBody {
+	/// The image(s) to edit. dall-e-2 takes a single square png image, less than 4MB,
+	/// gpt-image-1 up to 16 png, webp or jpg images, each less than 25MB.
+	/// If mask is not provided, the first image must have transparency, which will be used as the mask.
+	pub images: Vec<ImageInput>,
+	/// A text description of the desired image(s).
+	/// The maximum length is 1000 characters for dall-e-2 and 32000 characters for gpt-image-1.
+	pub prompt: String,
 	/// An additional image whose fully transparent areas (e.g. where alpha is zero)
-	/// indicate where image should be edited.
-	/// Must be a valid PNG file, less than 4MB, and have the same dimensions as image.
-	pub mask: Option<File>,
-	pub images_body: ImagesBody,
+	/// indicate where the first image should be edited.
+	/// Must be a valid png file, less than 4MB, and have the same dimensions as the image.
+	pub mask: Option<ImageInput>,
+	/// The model to use for image edits: dall-e-2 or gpt-image-1.
+	/// Defaults to dall-e-2
+	pub model: Option<String>,
+	/// The number of images to generate. Must be between 1 and 10.
+	/// Defaults to 1
+	pub n: Option<i32>,
+	/// The size of the generated images. Must be one of 256x256, 512x512, or 1024x1024 for dall-e-2,
+	/// and 1024x1024, 1536x1024, 1024x1536 or auto for gpt-image-1.
+	/// Defaults to 1024x1024
+	pub size: Option<String>,
+	/// The format in which the generated images are returned. Must be one of url or b64_json.
+	/// Not supported by gpt-image-1, which always returns b64_json.
+	/// Defaults to url
+	pub response_format: Option<String>,
+	/// The quality of the image, low, medium or high. Only supported by gpt-image-1.
+	/// Defaults to auto
+	pub quality: Option<String>,
+	/// The background of the generated images, transparent, opaque or auto.
+	/// Only supported by gpt-image-1.
+	/// Defaults to auto
+	pub background: Option<String>,
+	/// The format of the generated images, png, jpeg or webp. Only supported by gpt-image-1.
+	/// Defaults to png
+	pub output_format: Option<String>,
+	/// The compression level, from 0 to 100%, of the jpeg or webp generated images.
+	/// Only supported by gpt-image-1.
+	/// Defaults to 100
+	pub output_compression: Option<u8>,
+	/// A unique identifier representing your end-user, which can help OpenAI to monitor and detect abuse.
+	pub user: Option<String>,
+}
+
+impl ImageEditBody {
+	pub fn new(image: ImageInput, prompt: &str) -> ImageEditBody {
+		ImageEditBody::with_images(vec![image], prompt)
+	}
+
+	/// Edits several images at once, only supported by gpt-image-1.
+	pub fn with_images(images: Vec<ImageInput>, prompt: &str) -> ImageEditBody {
+		ImageEditBody {
+			images,
+			prompt: prompt.to_string(),
+			mask: None,
+			model: None,
+			n: None,
+			size: None,
+			response_format: None,
+			quality: None,
+			background: None,
+			output_format: None,
+			output_compression: None,
+			user: None,
+		}
+	}
+}
+
+const MB: usize = 1024 * 1024;
+/// The size limit of dall-e-2 images and of masks.
+const MAX_PNG_BYTES: usize = 4 * MB;
+/// The size limit of gpt-image-1 images.
+const MAX_IMAGE_BYTES: usize = 25 * MB;
+const MAX_EDIT_IMAGES: usize = 16;
+
+fn check_square(image: &ImageInput, info: PngInfo) -> ApiResult<()> {
+	if info.width != info.height {
+		return Err(image.invalid(format!("must be square, not {}x{}", info.width, info.height)));
+	}
+	Ok(())
+}
+
+fn check_mask(mask: &mut ImageInput, image: Option<PngInfo>) -> ApiResult<()> {
+	let info = mask.check_png(MAX_PNG_BYTES)?;
+	if !info.has_alpha {
+		return Err(mask.invalid("the mask has no transparent areas to edit"));
+	}
+	match image {
+		Some(image) if (image.width, image.height) != (info.width, info.height) => Err(mask
+			.invalid(format!(
+				"the mask is {}x{} but the image is {}x{}",
+				info.width, info.height, image.width, image.height
+			))),
+		_ => Ok(()),
+	}
+}
+
+impl ImageEditBody {
+	/// Checks the images against the limits of the model before uploading them, returning
+	/// [`Error::InvalidInput`] instead of spending a request on images the API would reject.
+	///
+	/// dall-e-2 takes a single square png of at most 4 MB, with transparency if there is no mask,
+	/// gpt-image-1 up to 16 png, jpeg or webp images of at most 25 MB.
+	/// The mask must be a png of at most 4 MB with transparency, the size of the first image.
+	/// The images are read into memory, but files and bytes over the limit are rejected without reading them.
+	pub fn validate(&mut self) -> ApiResult<()> {
+		let gpt_image = self.model.as_deref().is_some_and(|model| model.starts_with("gpt-image"));
+		let max_images = if gpt_image { MAX_EDIT_IMAGES } else { 1 };
+		if self.images.is_empty() || self.images.len() > max_images {
+			return Err(Error::InvalidInput(format!(
+				"{} images to edit, the model takes 1 to {}",
+				self.images.len(),
+				max_images
+			)));
+		}
+		let mut first = None;
+		for (i, image) in self.images.iter_mut().enumerate() {
+			let info = if gpt_image {
+				image.check_image(MAX_IMAGE_BYTES)?
+			} else {
+				let info = image.check_png(MAX_PNG_BYTES)?;
+				check_square(image, info)?;
+				if self.mask.is_none() && !info.has_alpha {
+					return Err(image.invalid("no transparent areas to edit, add a mask"));
+				}
+				Some(info)
+			};
+			if i == 0 {
+				first = info;
+			}
+		}
+		if let Some(mask) = self.mask.as_mut() {
+			check_mask(mask, first)?;
+		}
+		Ok(())
+	}
+}
+
+impl ImageVariationBody {
+	/// Checks the image is a square png of at most 4 MB before uploading it, returning
+	/// [`Error::InvalidInput`] otherwise. The image is read into memory,
+	/// but files and bytes over the limit are rejected without reading them.
+	pub fn validate(&mut self) -> ApiResult<()> {
+		let info = self.image.check_png(MAX_PNG_BYTES)?;
+		check_square(&self.image, info)
+	}
+}
+
+#[derive(Debug)]
+pub struct ImageVariationBody {
+	/// The image to use as the basis for the variation(s).
+	/// Must be a valid png file, less than 4MB, and square.
+	pub image: ImageInput,
+	/// The model to use for image variations. Only dall-e-2 is supported.
+	/// Defaults to dall-e-2
+	pub model: Option<String>,
+	/// The number of images to generate. Must be between 1 and 10.
+	/// Defaults to 1
+	pub n: Option<i32>,
+	/// The size of the generated images. Must be one of 256x256, 512x512, or 1024x1024.
+	/// Defaults to 1024x1024
+	pub size: Option<String>,
+	/// The format in which the generated images are returned. Must be one of url or b64_json.
+	/// Defaults to url
+	pub response_format: Option<String>,
+	/// A unique identifier representing your end-user, which can help OpenAI to monitor and detect abuse.
+	pub user: Option<String>,
+}
+
+impl ImageVariationBody {
+	pub fn new(image: ImageInput) -> ImageVariationBody {
+		ImageVariationBody {
+			image,
+			model: None,
+			n: None,
+			size: None,
+			response_format: None,
+			user: None,
+		}
+	}
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -49,139 +398,451 @@ pub struct Images {
 	pub data: Option<Vec<ImageData>>,
 }
 
+/// A generated image, either as a URL or base64 encoded, depending on the `response_format`.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ImageData {
-	pub url: String,
+	/// The URL of the image, valid for 60 minutes.
+	pub url: Option<String>,
+	/// The base64 encoded image, see [`ImageData::bytes`].
+	pub b64_json: Option<String>,
+	/// The prompt that was used to generate the image, if there was any revision to the prompt.
+	pub revised_prompt: Option<String>,
+}
+
+impl ImageData {
+	/// Decodes the `b64_json` image.
+	/// Images returned as a URL are not downloaded, and return an error.
+	pub fn bytes(&self) -> ApiResult<Vec<u8>> {
+		let b64_json = self.b64_json.as_deref().ok_or_else(|| {
+			Error::InvalidInput("No b64_json image, set response_format to b64_json".to_string())
+		})?;
+		base64::decode(b64_json).ok_or_else(|| Error::decode("Invalid base64 image", b64_json))
+	}
+
+	/// Decodes the `b64_json` image and writes it to `path`.
+	pub fn save<P: AsRef<Path>>(&self, path: P) -> ApiResult<()> {
+		std::fs::write(path, self.bytes()?)?;
+		Ok(())
+	}
 }
 
 pub trait ImagesApi {
 	/// Given a prompt and/or an input image, the model will generate a new image.
 	fn image_create(&self, images_body: &ImagesBody) -> ApiResult<Images>;
-	/// generates multipart data for image fns
-	fn image_build_send_data_from_body(
-		&self,
-		images_edit_body: ImagesEditBody,
-		url: &str,
-	) -> ApiResult<Images>;
-	/// Creates an edited or extended image given an original image and a prompt.
-	fn image_edit(&self, images_edit_body: ImagesEditBody) -> ApiResult<Images>;
+	/// Creates an edited or extended image given one or more source images and a prompt.
+	fn image_edit(&self, image_edit_body: ImageEditBody) -> ApiResult<Images>;
 	/// Creates a variation of a given image.
-	fn image_variation(&self, images_edit_body: ImagesEditBody) -> ApiResult<Images>;
+	fn image_variation(&self, image_variation_body: ImageVariationBody) -> ApiResult<Images>;
 }
 
 impl ImagesApi for OpenAI {
 	fn image_create(&self, images_body: &ImagesBody) -> ApiResult<Images> {
-		let request_body = serde_json::to_value(images_body).unwrap();
+		let request_body = to_json(images_body)?;
 		let res = self.post(IMAGES_CREATE, request_body)?;
-		let images: Images = serde_json::from_value(res.clone()).unwrap();
+		let images: Images = from_json(&res)?;
 		Ok(images)
 	}
 
-	fn image_build_send_data_from_body(
-		&self,
-		images_edit_body: ImagesEditBody,
-		url: &str,
-	) -> ApiResult<Images> {
-		let mut send_data = Multipart::new();
+	fn image_edit(&self, image_edit_body: ImageEditBody) -> ApiResult<Images> {
+		let send_data = build_edit_multipart(image_edit_body)?;
+		let res = self.post_multipart(IMAGES_EDIT, send_data)?;
+		let images: Images = from_json(&res)?;
+		Ok(images)
+	}
 
-		if IMAGES_EDIT == url {
-			send_data.add_text("prompt", images_edit_body.images_body.prompt);
-		}
-		if let Some(n) = images_edit_body.images_body.n {
-			send_data.add_text("n", n.to_string());
-		}
-		if let Some(size) = images_edit_body.images_body.size {
-			send_data.add_text("size", size.to_string());
-		}
-		if let Some(response_format) = images_edit_body.images_body.response_format {
-			send_data.add_text("response_format", response_format.to_string());
-		}
-		if let Some(user) = images_edit_body.images_body.user {
-			send_data.add_text("user", user.to_string());
-		}
-		if let Some(mask) = images_edit_body.mask {
-			send_data.add_stream("mask", mask, Some("blob"), Some(mime::IMAGE_PNG));
-		}
-		send_data.add_stream("image", images_edit_body.image, Some("blob"), Some(mime::IMAGE_PNG));
+	fn image_variation(&self, image_variation_body: ImageVariationBody) -> ApiResult<Images> {
+		let send_data = build_variation_multipart(image_variation_body);
+		let res = self.post_multipart(IMAGES_VARIATIONS, send_data)?;
+		let images: Images = from_json(&res)?;
+		Ok(images)
+	}
+}
+
+fn build_edit_multipart(body: ImageEditBody) -> ApiResult<Multipart<'static, 'static>> {
+	let mut send_data = Multipart::new();
+
+	send_data.add_text("prompt", body.prompt);
+	if let Some(model) = body.model {
+		send_data.add_text("model", model);
+	}
+	if let Some(n) = body.n {
+		send_data.add_text("n", n.to_string());
+	}
+	if let Some(size) = body.size {
+		send_data.add_text("size", size);
+	}
+	if let Some(response_format) = body.response_format {
+		send_data.add_text("response_format", response_format);
+	}
+	if let Some(quality) = body.quality {
+		send_data.add_text("quality", quality);
+	}
+	if let Some(background) = body.background {
+		send_data.add_text("background", background);
+	}
+	if let Some(output_format) = body.output_format {
+		send_data.add_text("output_format", output_format);
+	}
+	if let Some(output_compression) = body.output_compression {
+		send_data.add_text("output_compression", output_compression.to_string());
+	}
+	if let Some(user) = body.user {
+		send_data.add_text("user", user);
+	}
+	if let Some(mask) = body.mask {
+		mask.add_to(&mut send_data, "mask");
+	}
+	// A single image keeps the field name dall-e-2 expects.
+	let name = match body.images.len() {
+		0 => return Err(Error::InvalidInput("No image to edit".to_string())),
+		1 => "image",
+		_ => "image[]",
+	};
+	for image in body.images {
+		image.add_to(&mut send_data, name);
+	}
+	Ok(send_data)
+}
+
+fn build_variation_multipart(body: ImageVariationBody) -> Multipart<'static, 'static> {
+	let mut send_data = Multipart::new();
+
+	if let Some(model) = body.model {
+		send_data.add_text("model", model);
+	}
+	if let Some(n) = body.n {
+		send_data.add_text("n", n.to_string());
+	}
+	if let Some(size) = body.size {
+		send_data.add_text("size", size);
+	}
+	if let Some(response_format) = body.response_format {
+		send_data.add_text("response_format", response_format);
+	}
+	if let Some(user) = body.user {
+		send_data.add_text("user", user);
+	}
+	body.image.add_to(&mut send_data, "image");
+	send_data
+}
+
+/// The async counterpart of [`ImagesApi`].
+#[cfg(feature = "async")]
+pub trait AsyncImagesApi {
+	/// Given a prompt and/or an input image, the model will generate a new image.
+	fn image_create(
+		&self,
+		images_body: &ImagesBody,
+	) -> impl Future<Output = ApiResult<Images>> + Send;
+	/// Creates an edited or extended image given one or more source images and a prompt.
+	fn image_edit(
+		&self,
+		image_edit_body: ImageEditBody,
+	) -> impl Future<Output = ApiResult<Images>> + Send;
+	/// Creates a variation of a given image.
+	fn image_variation(
+		&self,
+		image_variation_body: ImageVariationBody,
+	) -> impl Future<Output = ApiResult<Images>> + Send;
+}
 
-		let res = self.post_multipart(url, send_data)?;
-		let images: Images = serde_json::from_value(res.clone()).unwrap();
+#[cfg(feature = "async")]
+impl AsyncImagesApi for AsyncOpenAI {
+	async fn image_create(&self, images_body: &ImagesBody) -> ApiResult<Images> {
+		let request_body = to_json(images_body)?;
+		let res = self.post(IMAGES_CREATE, request_body).await?;
+		let images: Images = from_json(&res)?;
 		Ok(images)
 	}
 
-	fn image_edit(&self, images_edit_body: ImagesEditBody) -> ApiResult<Images> {
-		self.image_build_send_data_from_body(images_edit_body, IMAGES_EDIT)
+	async fn image_edit(&self, image_edit_body: ImageEditBody) -> ApiResult<Images> {
+		let form_data = FormData::new(build_edit_multipart(image_edit_body)?)?;
+		let res = self.post_multipart(IMAGES_EDIT, form_data).await?;
+		let images: Images = from_json(&res)?;
+		Ok(images)
 	}
 
-	fn image_variation(&self, images_edit_body: ImagesEditBody) -> ApiResult<Images> {
-		self.image_build_send_data_from_body(images_edit_body, IMAGES_VARIATIONS)
+	async fn image_variation(&self, image_variation_body: ImageVariationBody) -> ApiResult<Images> {
+		let form_data = FormData::new(build_variation_multipart(image_variation_body))?;
+		let res = self.post_multipart(IMAGES_VARIATIONS, form_data).await?;
+		let images: Images = from_json(&res)?;
+		Ok(images)
 	}
 }
 
 #[cfg(test)]
 mod tests {
-	use std::fs::File;
+	use std::io::{self, Read};
+
+	use serde_json::json;
 
 	use crate::{
-		apis::images::{ImagesApi, ImagesBody, ImagesEditBody},
-		openai::new_test_openai,
+		apis::images::{ImageEditBody, ImageInput, ImageVariationBody, ImagesApi, ImagesBody},
+		openai::new_mock_openai,
+		png, Error, MockResponse,
 	};
 
+	/// A response with two image URLs.
+	fn url_response() -> MockResponse {
+		MockResponse::json(
+			200,
+			json!({
+				"created": 1713833628,
+				"data": [
+					{ "url": "https://example.com/images/otter-1.png" },
+					{ "url": "https://example.com/images/otter-2.png" }
+				]
+			}),
+		)
+	}
+
 	#[test]
 	fn test_image_create() {
-		let openai = new_test_openai();
+		let (openai, transport) = new_mock_openai();
+		transport.push(url_response());
 		let body = ImagesBody {
 			prompt: "A cute baby sea otter".to_string(),
 			n: Some(2),
 			size: Some("1024x1024".to_string()),
-			response_format: None,
-			user: None,
+			..Default::default()
 		};
 		let rs = openai.image_create(&body);
 		let images = rs.unwrap().data.unwrap();
-		let image = images.get(0).unwrap();
-		assert!(image.url.contains("http"));
+		assert_eq!(images.len(), 2);
+		let image = images.first().unwrap();
+		assert!(image.url.as_ref().unwrap().contains("http"));
+		assert!(matches!(image.bytes(), Err(Error::InvalidInput(_))));
+
+		let request = &transport.requests()[0];
+		assert_eq!(request.url, "https://api.openai.com/v1/images/generations");
+		assert_eq!(
+			request.json().unwrap(),
+			json!({ "prompt": "A cute baby sea otter", "n": 2, "size": "1024x1024" })
+		);
+	}
+
+	#[test]
+	fn test_image_create_b64_json() {
+		let (openai, transport) = new_mock_openai();
+		transport.push(MockResponse::json(
+			200,
+			json!({
+				"created": 1713833628,
+				"data": [{ "b64_json": "iVBORw0KGgo=", "revised_prompt": "A cute baby sea otter, floating" }],
+				"usage": { "total_tokens": 100, "input_tokens": 50, "output_tokens": 50 }
+			}),
+		));
+		let body = ImagesBody {
+			prompt: "A cute baby sea otter".to_string(),
+			model: Some("gpt-image-1".to_string()),
+			quality: Some("high".to_string()),
+			background: Some("transparent".to_string()),
+			output_format: Some("webp".to_string()),
+			output_compression: Some(80),
+			..Default::default()
+		};
+		let images = openai.image_create(&body).unwrap().data.unwrap();
+		let image = images.first().unwrap();
+		assert_eq!(image.revised_prompt.as_deref(), Some("A cute baby sea otter, floating"));
+		assert_eq!(image.bytes().unwrap(), b"\x89PNG\r\n\x1a\n");
+
+		let path = std::env::temp_dir().join(format!("image-{}.png", std::process::id()));
+		image.save(&path).unwrap();
+		assert_eq!(std::fs::read(&path).unwrap(), b"\x89PNG\r\n\x1a\n");
+		std::fs::remove_file(path).unwrap();
+
+		assert_eq!(
+			transport.requests()[0].json().unwrap(),
+			json!({
+				"prompt": "A cute baby sea otter",
+				"model": "gpt-image-1",
+				"quality": "high",
+				"background": "transparent",
+				"output_format": "webp",
+				"output_compression": 80
+			})
+		);
 	}
 
 	#[test]
 	fn test_image_edit() {
-		let openai = new_test_openai();
-		let file = File::open("test_files/image.png").unwrap();
-		let multipart = ImagesEditBody {
-			images_body: ImagesBody {
-				prompt: "A cute baby sea otter wearing a beret".to_string(),
-				n: Some(2),
-				size: Some("1024x1024".to_string()),
-				response_format: None,
-				user: None,
-			},
-			image: file,
-			mask: None,
+		let (openai, transport) = new_mock_openai();
+		transport.push(url_response());
+		let file = ImageInput::from_path("test_files/image.png").unwrap();
+		let body = ImageEditBody {
+			n: Some(2),
+			size: Some("1024x1024".to_string()),
+			..ImageEditBody::new(file, "A cute baby sea otter wearing a beret")
 		};
-		let rs = openai.image_edit(multipart);
+		let rs = openai.image_edit(body);
 		let images = rs.unwrap().data.unwrap();
-		let image = images.get(0).unwrap();
-		assert!(image.url.contains("http"));
+		let image = images.first().unwrap();
+		assert!(image.url.as_ref().unwrap().contains("http"));
+
+		let request = &transport.requests()[0];
+		assert_eq!(request.url, "https://api.openai.com/v1/images/edits");
+		let form = String::from_utf8_lossy(request.body.as_ref().unwrap()).into_owned();
+		assert!(form.contains("name=\"prompt\"\r\n\r\nA cute baby sea otter wearing a beret\r\n"));
+		assert!(form.contains("name=\"n\"\r\n\r\n2\r\n"));
+		assert!(form.contains("name=\"size\"\r\n\r\n1024x1024\r\n"));
 	}
 
 	#[test]
 	fn test_image_variations() {
-		let openai = new_test_openai();
-		let file = File::open("test_files/image.png").unwrap();
-		let multipart = ImagesEditBody {
-			images_body: ImagesBody {
-				prompt: "".to_string(),
-				n: Some(2),
-				size: Some("1024x1024".to_string()),
-				response_format: None,
-				user: None,
-			},
-			image: file,
-			mask: None,
+		let (openai, transport) = new_mock_openai();
+		transport.push(url_response());
+		let file = ImageInput::from_path("test_files/image.png").unwrap();
+		let body = ImageVariationBody {
+			n: Some(2),
+			size: Some("1024x1024".to_string()),
+			..ImageVariationBody::new(file)
 		};
-		let rs = openai.image_variation(multipart);
+		let rs = openai.image_variation(body);
 		let images = rs.unwrap().data.unwrap();
-		let image = images.get(0).unwrap();
-		assert!(image.url.contains("http"));
+		let image = images.first().unwrap();
+		assert!(image.url.as_ref().unwrap().contains("http"));
+		assert_eq!(transport.requests()[0].url, "https://api.openai.com/v1/images/variations");
+	}
+
+	#[test]
+	fn test_image_edit_multiple_images() {
+		let (openai, transport) = new_mock_openai();
+		transport.push(MockResponse::json(
+			200,
+			json!({ "created": 1713833628, "data": [{ "b64_json": "iVBORw0KGgo=" }] }),
+		));
+		let body = ImageEditBody {
+			model: Some("gpt-image-1".to_string()),
+			mask: Some(ImageInput::from_bytes(b"mask".to_vec(), "mask.png")),
+			..ImageEditBody::with_images(
+				vec![
+					ImageInput::from_bytes(b"jpeg".to_vec(), "soap.JPG"),
+					ImageInput::from_bytes(b"webp".to_vec(), "lotion.webp"),
+				],
+				"A gift basket",
+			)
+		};
+		openai.image_edit(body).unwrap();
+
+		let request = &transport.requests()[0];
+		assert_eq!(request.url, "https://api.openai.com/v1/images/edits");
+		let form = String::from_utf8_lossy(request.body.as_ref().unwrap()).into_owned();
+		assert!(form.contains("name=\"prompt\"\r\n\r\nA gift basket\r\n"));
+		assert!(form.contains("name=\"model\"\r\n\r\ngpt-image-1\r\n"));
+		assert!(form.contains(
+			"name=\"mask\"; filename=\"mask.png\"\r\nContent-Type: image/png\r\n\r\nmask"
+		));
+		assert!(form.contains(
+			"name=\"image[]\"; filename=\"soap.JPG\"\r\nContent-Type: image/jpeg\r\n\r\njpeg"
+		));
+		assert!(form.contains(
+			"name=\"image[]\"; filename=\"lotion.webp\"\r\nContent-Type: image/webp\r\n\r\nwebp"
+		));
+	}
+
+	#[test]
+	fn test_image_variation_input() {
+		let (openai, transport) = new_mock_openai();
+		transport.push(MockResponse::json(
+			200,
+			json!({ "created": 1713833628, "data": [{ "url": "https://example.com/otter.png" }] }),
+		));
+		let body = ImageVariationBody::new(ImageInput::from_bytes(b"png".to_vec(), "otter.png"));
+		openai.image_variation(body).unwrap();
+
+		let request = &transport.requests()[0];
+		assert_eq!(request.url, "https://api.openai.com/v1/images/variations");
+		let form = String::from_utf8_lossy(request.body.as_ref().unwrap()).into_owned();
+		assert!(!form.contains("name=\"prompt\""));
+		assert!(form.contains(
+			"name=\"image\"; filename=\"otter.png\"\r\nContent-Type: image/png\r\n\r\npng"
+		));
+
+		let input = ImageInput::from_bytes(vec![], "otter.bmp");
+		assert_eq!(input.mime(), &mime::APPLICATION_OCTET_STREAM);
+		assert_eq!(input.with_mime(mime::IMAGE_BMP).mime(), &mime::IMAGE_BMP);
+		let rs = openai.image_edit(ImageEditBody::with_images(vec![], "empty"));
+		assert!(matches!(rs, Err(Error::InvalidInput(_))));
+	}
+
+	fn png(width: u32, height: u32, has_alpha: bool) -> ImageInput {
+		let color_type = if has_alpha { 6 } else { 2 };
+		ImageInput::from_bytes(png::write_header(width, height, color_type), "image.png")
+	}
+
+	fn invalid_input(body: &mut ImageEditBody) -> String {
+		match body.validate() {
+			Err(Error::InvalidInput(message)) => message,
+			rs => panic!("expected an invalid input, got {:?}", rs),
+		}
+	}
+
+	#[test]
+	fn test_image_edit_validate() {
+		let mut body = ImageEditBody::new(png(512, 512, true), "A beret");
+		body.validate().unwrap();
+
+		let mut body = ImageEditBody::new(png(512, 256, true), "A beret");
+		assert_eq!(invalid_input(&mut body), "image.png: must be square, not 512x256");
+		let mut body = ImageEditBody::new(png(512, 512, false), "A beret");
+		assert_eq!(invalid_input(&mut body), "image.png: no transparent areas to edit, add a mask");
+		let jpeg = || ImageInput::from_bytes(b"\xff\xd8\xff\xe0".to_vec(), "image.jpg");
+		let mut body = ImageEditBody::new(jpeg(), "A beret");
+		assert_eq!(invalid_input(&mut body), "image.jpg: not a PNG file");
+		let large = ImageInput::from_bytes(vec![0; 4 * 1024 * 1024 + 1], "large.png");
+		let mut body = ImageEditBody::new(large, "A beret");
+		assert_eq!(invalid_input(&mut body), "large.png: larger than the 4 MB limit");
+		let large = ImageInput::from_reader(io::repeat(0).take(4 * 1024 * 1024 + 1), "large.png");
+		let mut body = ImageEditBody::new(large, "A beret");
+		assert_eq!(invalid_input(&mut body), "large.png: larger than the 4 MB limit");
+		// The oversized image is still whole, to upload it anyway.
+		let mut bytes = Vec::new();
+		body.images[0].reader.read_to_end(&mut bytes).unwrap();
+		assert_eq!(bytes.len(), 4 * 1024 * 1024 + 1);
+
+		let mut body = ImageEditBody {
+			mask: Some(png(256, 256, true)),
+			..ImageEditBody::new(png(512, 512, false), "A beret")
+		};
+		assert_eq!(
+			invalid_input(&mut body),
+			"image.png: the mask is 256x256 but the image is 512x512"
+		);
+		let mut body = ImageEditBody {
+			mask: Some(png(512, 512, false)),
+			..ImageEditBody::new(png(512, 512, false), "A beret")
+		};
+		assert_eq!(
+			invalid_input(&mut body),
+			"image.png: the mask has no transparent areas to edit"
+		);
+
+		let mut body = ImageEditBody::with_images(vec![png(512, 512, true), jpeg()], "A beret");
+		assert_eq!(invalid_input(&mut body), "2 images to edit, the model takes 1 to 1");
+		body.model = Some("gpt-image-1".to_string());
+		body.validate().unwrap();
+		body.images.push(ImageInput::from_bytes(b"GIF89a".to_vec(), "image.gif"));
+		assert_eq!(invalid_input(&mut body), "image.gif: not a png, jpeg or webp image");
+
+		let mut body = ImageVariationBody::new(png(512, 256, false));
+		assert!(matches!(body.validate(), Err(Error::InvalidInput(_))));
+	}
+
+	#[test]
+	fn test_image_edit_validate_upload() {
+		let (openai, transport) = new_mock_openai();
+		transport.push(MockResponse::json(
+			200,
+			json!({ "created": 1713833628, "data": [{ "url": "https://example.com/otter.png" }] }),
+		));
+		let mut body = ImageVariationBody::new(png(512, 512, false));
+		body.validate().unwrap();
+		body.validate().unwrap();
+		openai.image_variation(body).unwrap();
+
+		let request = &transport.requests()[0];
+		let form = request.body.as_ref().unwrap();
+		let image = png::write_header(512, 512, 2);
+		assert!(form.windows(image.len()).any(|window| window == image));
 	}
 }
@@ -1,11 +1,20 @@
-use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{ApiResult, Error, Json};
 
 pub mod audio;
+pub mod batches;
 pub mod chat;
 pub mod completions;
 pub mod embeddings;
+pub mod files;
+pub mod fine_tuning;
 pub mod images;
 pub mod models;
+pub mod moderations;
+pub mod tools;
 
 // Models API
 const MODELS_LIST: &str = "models";
@@ -23,32 +32,195 @@ const EMBEDDINGS_CREATE: &str = "embeddings";
 // Audio API
 const AUDIO_TRANSCRIPTION_CREATE: &str = "audio/transcriptions";
 const AUDIO_TRANSLATIONS_CREATE: &str = "audio/translations";
+const AUDIO_SPEECH_CREATE: &str = "audio/speech";
+// Files API
+const FILES: &str = "files";
+const FILES_RETRIEVE: &str = "files/";
+// Fine-tuning API
+const FINE_TUNING_JOBS: &str = "fine_tuning/jobs";
+const FINE_TUNING_JOBS_RETRIEVE: &str = "fine_tuning/jobs/";
+// Batch API
+const BATCHES: &str = "batches";
+const BATCHES_RETRIEVE: &str = "batches/";
+// Moderations API
+const MODERATIONS_CREATE: &str = "moderations";
 
-#[derive(Debug, Serialize, Deserialize)]
+/// The APIs which Azure OpenAI serves under `openai/deployments/{deployment}/`.
+pub(crate) const DEPLOYMENT_APIS: &[&str] = &[
+	COMPLETION_CREATE,
+	CHAT_COMPLETION_CREATE,
+	IMAGES_CREATE,
+	IMAGES_EDIT,
+	IMAGES_VARIATIONS,
+	EMBEDDINGS_CREATE,
+	AUDIO_TRANSCRIPTION_CREATE,
+	AUDIO_TRANSLATIONS_CREATE,
+	AUDIO_SPEECH_CREATE,
+];
+
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct Usage {
 	pub prompt_tokens: Option<u32>,
 	pub completion_tokens: Option<u32>,
 	pub total_tokens: Option<u32>,
 }
 
+/// Options for streamed responses.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StreamOptions {
+	/// Sends a last chunk before `data: [DONE]`, with the usage of the whole request and no choices.
+	pub include_usage: bool,
+}
+
+/// Cursor-based pagination for the list endpoints.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ListParams {
+	/// The ID of the last object of the previous page, to get the page after it.
+	pub after: Option<String>,
+	/// The number of objects to return.
+	/// Defaults to 20, or 10000 for files
+	pub limit: Option<u32>,
+}
+
+impl ListParams {
+	/// The params for the page after `page`, or `None` if `page` is the last one.
+	pub fn next<T: HasId>(&self, page: &Page<T>) -> Option<ListParams> {
+		if !page.has_more {
+			return None;
+		}
+		let last = page.data.last()?;
+		Some(ListParams { after: Some(last.id().to_string()), limit: self.limit })
+	}
+
+	/// Appends the params to the query string of `url`.
+	pub(crate) fn url(&self, url: &str) -> String {
+		let mut query = Vec::new();
+		if let Some(after) = &self.after {
+			query.push(format!("after={}", encode_query(after)));
+		}
+		if let Some(limit) = self.limit {
+			query.push(format!("limit={limit}"));
+		}
+		if query.is_empty() {
+			url.to_string()
+		} else {
+			let separator = if url.contains('?') { '&' } else { '?' };
+			format!("{url}{separator}{}", query.join("&"))
+		}
+	}
+}
+
+/// Percent-encodes a query string value, keeping only the unreserved characters of RFC 3986.
+pub(crate) fn encode_query(value: &str) -> String {
+	let mut encoded = String::with_capacity(value.len());
+	for byte in value.bytes() {
+		match byte {
+			b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+				encoded.push(byte as char)
+			},
+			_ => encoded.push_str(&format!("%{byte:02X}")),
+		}
+	}
+	encoded
+}
+
+/// A page of a list endpoint.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Page<T> {
+	pub object: Option<String>,
+	pub data: Vec<T>,
+	/// Whether there are more objects after this page.
+	#[serde(default)]
+	pub has_more: bool,
+}
+
+/// The objects which can be listed with [`ListParams`].
+pub trait HasId {
+	fn id(&self) -> &str;
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Choice {
 	pub text: Option<String>,
 	pub index: u32,
-	pub logprobs: Option<String>,
+	/// Log probability information, when requested through `logprobs`.
+	pub logprobs: Option<Json>,
 	pub finish_reason: Option<String>,
 	pub message: Option<Message>,
 }
 
+impl Choice {
+	/// The tools the model called in this choice, if any.
+	pub fn tool_calls(&self) -> &[ToolCall] {
+		self.message.as_ref().and_then(|m| m.tool_calls.as_deref()).unwrap_or_default()
+	}
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Message {
 	pub role: Role,
-	pub content: String,
+	/// The contents of the message.
+	/// May be omitted for `assistant` messages that only call tools.
+	pub content: Option<Content>,
+	/// An optional name for the participant.
+	/// Required for `function` messages, the name of the function.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub name: Option<String>,
+	/// The tool calls generated by the model.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub tool_calls: Option<Vec<ToolCall>>,
+	/// For `tool` messages, the tool call that this message is responding to.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub tool_call_id: Option<String>,
+	/// The refusal message generated by the model instead of the requested structured output.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub refusal: Option<String>,
+}
+
+impl Message {
+	pub fn new(role: Role, content: &str) -> Message {
+		Message {
+			role,
+			content: Some(Content::Text(content.to_string())),
+			name: None,
+			tool_calls: None,
+			tool_call_id: None,
+			refusal: None,
+		}
+	}
+
+	/// A message mixing text and images. Only supported for `user` messages.
+	pub fn with_parts(role: Role, parts: Vec<ContentPart>) -> Message {
+		Message { content: Some(Content::Parts(parts)), ..Message::new(role, "") }
+	}
+
+	/// The text of the message, if its content is plain text.
+	pub fn text(&self) -> Option<&str> {
+		match &self.content {
+			Some(Content::Text(text)) => Some(text),
+			_ => None,
+		}
+	}
+
+	/// A `tool` message carrying the result of the tool call `tool_call_id`.
+	pub fn tool(tool_call_id: &str, content: &str) -> Message {
+		Message {
+			tool_call_id: Some(tool_call_id.to_string()),
+			..Message::new(Role::Tool, content)
+		}
+	}
 }
 
 impl Clone for Message {
 	fn clone(&self) -> Self {
-		Self { role: self.role.clone(), content: self.content.clone() }
+		Self {
+			role: self.role.clone(),
+			content: self.content.clone(),
+			name: self.name.clone(),
+			tool_calls: self.tool_calls.clone(),
+			tool_call_id: self.tool_call_id.clone(),
+			refusal: self.refusal.clone(),
+		}
 	}
 }
 
@@ -58,6 +230,9 @@ pub enum Role {
 	System,
 	Assistant,
 	User,
+	Tool,
+	/// Deprecated in favor of `Tool`.
+	Function,
 }
 
 impl Clone for Role {
@@ -66,6 +241,121 @@ impl Clone for Role {
 			Self::System => Self::System,
 			Self::Assistant => Self::Assistant,
 			Self::User => Self::User,
+			Self::Tool => Self::Tool,
+			Self::Function => Self::Function,
 		}
 	}
 }
+
+/// The contents of a message, either plain text or a list of typed parts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Content {
+	Text(String),
+	Parts(Vec<ContentPart>),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentPart {
+	Text { text: String },
+	ImageUrl { image_url: ImageUrl },
+}
+
+impl ContentPart {
+	pub fn text(text: &str) -> ContentPart {
+		ContentPart::Text { text: text.to_string() }
+	}
+
+	/// An image part from either a URL or a base64 encoded data URI.
+	pub fn image_url(url: &str, detail: Option<ImageDetail>) -> ContentPart {
+		ContentPart::ImageUrl { image_url: ImageUrl { url: url.to_string(), detail } }
+	}
+
+	/// An image part embedding `data` as a base64 data URI.
+	pub fn image_bytes(data: &[u8], mime: &mime::Mime, detail: Option<ImageDetail>) -> ContentPart {
+		let url = format!("data:{};base64,{}", mime, crate::base64::encode(data));
+		ContentPart::ImageUrl { image_url: ImageUrl { url, detail } }
+	}
+
+	/// An image part embedding a local PNG, JPEG, WEBP or GIF file as a base64 data URI.
+	/// The MIME type is derived from the file extension.
+	pub fn image_file<P: AsRef<Path>>(
+		path: P,
+		detail: Option<ImageDetail>,
+	) -> ApiResult<ContentPart> {
+		let path = path.as_ref();
+		let filename = path.file_name().unwrap_or_default().to_string_lossy();
+		let mime = images::image_mime(&filename).ok_or_else(|| {
+			Error::InvalidInput(format!("Unsupported image type: {}", path.display()))
+		})?;
+		let data = std::fs::read(path)?;
+		Ok(ContentPart::image_bytes(&data, &mime, detail))
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageUrl {
+	/// Either a URL of the image or the base64 encoded image data.
+	pub url: String,
+	/// Specifies the detail level of the image.
+	/// Defaults to auto
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub detail: Option<ImageDetail>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageDetail {
+	Auto,
+	Low,
+	High,
+}
+
+/// A call to a tool generated by the model.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolCall {
+	/// The ID of the tool call, to be sent back in the `tool` message with the result.
+	pub id: String,
+	/// The type of the tool. Currently, only `function` is supported.
+	#[serde(rename = "type")]
+	pub tool_type: String,
+	pub function: FunctionCall,
+}
+
+/// The function the model wants to call.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FunctionCall {
+	/// The name of the function to call.
+	pub name: String,
+	/// The arguments to call the function with, as generated by the model in JSON format.
+	/// Note that the model does not always generate valid JSON,
+	/// and may hallucinate parameters not defined by your function schema.
+	pub arguments: String,
+}
+
+impl FunctionCall {
+	/// Parses the JSON arguments generated by the model.
+	pub fn parse_arguments<T: DeserializeOwned>(&self) -> ApiResult<T> {
+		serde_json::from_str(&self.arguments).map_err(|e| {
+			Error::ParseError(format!(
+				"Invalid arguments for {}: {e}, {}",
+				self.name, self.arguments
+			))
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::ListParams;
+
+	#[test]
+	fn test_list_params_url() {
+		assert_eq!(ListParams::default().url("batches"), "batches");
+		let params = ListParams { after: Some("a b&c=d/é".to_string()), limit: Some(5) };
+		assert_eq!(params.url("batches"), "batches?after=a%20b%26c%3Dd%2F%C3%A9&limit=5");
+		let params = ListParams { after: None, limit: Some(5) };
+		assert_eq!(params.url("files?purpose=batch"), "files?purpose=batch&limit=5");
+	}
+}
@@ -4,13 +4,16 @@
 // See: https://platform.openai.com/docs/api-reference/models
 
 //! Models API
-use crate::requests::Requests;
+use crate::requests::{from_json, Requests};
 use crate::*;
 use serde::{Deserialize, Serialize};
 
 use super::MODELS_LIST;
 use super::MODELS_RETRIEVE;
 
+#[cfg(feature = "async")]
+use {crate::async_requests::AsyncRequests, std::future::Future};
+
 /// List and describe the various models available in the API.
 /// You can refer to the [Models](https://platform.openai.com/docs/models) documentation
 /// to understand what models are available and the differences between them.
@@ -33,36 +36,94 @@ pub trait ModelsApi {
 impl ModelsApi for OpenAI {
 	fn models_list(&self) -> ApiResult<Vec<Model>> {
 		let res: Json = self.get(MODELS_LIST)?;
-		let data = res.as_object().unwrap().get("data");
-		if let Some(data) = data {
-			let models: Vec<Model> = serde_json::from_value(data.clone()).unwrap();
-			return Ok(models);
+		match res.get("data") {
+			Some(data) => from_json(data),
+			None => Err(Error::decode("No data", &res.to_string())),
 		}
-		Err(Error::ApiError("No data".to_string()))
 	}
 
 	fn models_retrieve(&self, model_id: &str) -> ApiResult<Model> {
 		let res: Json = self.get(&(MODELS_RETRIEVE.to_owned() + model_id))?;
-		let model: Model = serde_json::from_value(res).unwrap();
+		let model: Model = from_json(&res)?;
+		Ok(model)
+	}
+}
+
+/// The async counterpart of [`ModelsApi`].
+#[cfg(feature = "async")]
+pub trait AsyncModelsApi {
+	/// Lists the currently available models,
+	/// and provides basic information about each one such as the owner and availability.
+	fn models_list(&self) -> impl Future<Output = ApiResult<Vec<Model>>> + Send;
+	/// Retrieves a model instance,
+	/// providing basic information about the model such as the owner and permissioning.
+	fn models_retrieve(&self, model_id: &str) -> impl Future<Output = ApiResult<Model>> + Send;
+}
+
+#[cfg(feature = "async")]
+impl AsyncModelsApi for AsyncOpenAI {
+	async fn models_list(&self) -> ApiResult<Vec<Model>> {
+		let res: Json = self.get(MODELS_LIST).await?;
+		match res.get("data") {
+			Some(data) => from_json(data),
+			None => Err(Error::decode("No data", &res.to_string())),
+		}
+	}
+
+	async fn models_retrieve(&self, model_id: &str) -> ApiResult<Model> {
+		let res: Json = self.get(&(MODELS_RETRIEVE.to_owned() + model_id)).await?;
+		let model: Model = from_json(&res)?;
 		Ok(model)
 	}
 }
 
 #[cfg(test)]
 mod tests {
-	use crate::{apis::models::ModelsApi, openai::new_test_openai};
+	use serde_json::json;
+
+	use crate::{apis::models::ModelsApi, openai::new_mock_openai, MockResponse};
+
+	fn model(id: &str, owned_by: &str) -> serde_json::Value {
+		json!({ "id": id, "object": "model", "created": 1692634615, "owned_by": owned_by })
+	}
 
 	#[test]
 	fn test_models() {
-		let openai = new_test_openai();
+		let (openai, transport) = new_mock_openai();
+		transport.push(MockResponse::json(
+			200,
+			json!({ "object": "list", "data": [model("babbage-002", "system"), model("whisper-1", "openai-internal")] }),
+		));
 		let models = openai.models_list().unwrap();
-		assert!(!models.is_empty());
+		assert_eq!(models.len(), 2);
+		assert_eq!(models[1].id, "whisper-1");
+		assert_eq!(transport.requests()[0].url, "https://api.openai.com/v1/models");
 	}
 
 	#[test]
 	fn test_get_model() {
-		let openai = new_test_openai();
+		let (openai, transport) = new_mock_openai();
+		transport.push(MockResponse::json(200, model("babbage-002", "system")));
 		let model = openai.models_retrieve("babbage-002").unwrap();
 		assert_eq!("babbage-002", model.id);
+		assert_eq!(transport.requests()[0].url, "https://api.openai.com/v1/models/babbage-002");
+	}
+
+	#[cfg(feature = "async")]
+	#[tokio::test]
+	async fn test_async_models() {
+		use crate::{apis::models::AsyncModelsApi, openai::new_mock_async_openai};
+
+		let (openai, transport) = new_mock_async_openai();
+		transport
+			.push(MockResponse::json(
+				200,
+				json!({ "object": "list", "data": [model("babbage-002", "system")] }),
+			))
+			.push(MockResponse::json(200, model("babbage-002", "system")));
+		let models = openai.models_list().await.unwrap();
+		assert!(!models.is_empty());
+		let model = openai.models_retrieve("babbage-002").await.unwrap();
+		assert_eq!("babbage-002", model.id);
 	}
 }
@@ -0,0 +1,272 @@
+// Given text and/or image inputs, classifies if those inputs are potentially harmful.
+// See: https://platform.openai.com/docs/api-reference/moderations
+
+//! Moderations API
+
+use serde::{Deserialize, Serialize};
+
+use crate::requests::{from_json, to_json, Requests};
+use crate::*;
+
+use super::MODERATIONS_CREATE;
+
+#[cfg(feature = "async")]
+use {crate::async_requests::AsyncRequests, std::future::Future};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModerationBody {
+	/// Input (or inputs) to classify.
+	pub input: ModerationInput,
+	/// The content moderation model you would like to use,
+	/// `omni-moderation-latest` also classifies images.
+	/// Defaults to omni-moderation-latest
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub model: Option<String>,
+}
+
+impl ModerationBody {
+	pub fn new(input: impl Into<ModerationInput>) -> ModerationBody {
+		ModerationBody { input: input.into(), model: None }
+	}
+}
+
+/// A single string, an array of strings, or an array of text and image parts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ModerationInput {
+	Text(String),
+	Texts(Vec<String>),
+	/// Text and image parts, only supported by the omni moderation models.
+	Parts(Vec<ContentPart>),
+}
+
+impl From<&str> for ModerationInput {
+	fn from(text: &str) -> Self {
+		ModerationInput::Text(text.to_string())
+	}
+}
+
+impl From<String> for ModerationInput {
+	fn from(text: String) -> Self {
+		ModerationInput::Text(text)
+	}
+}
+
+impl From<Vec<String>> for ModerationInput {
+	fn from(texts: Vec<String>) -> Self {
+		ModerationInput::Texts(texts)
+	}
+}
+
+impl From<Vec<ContentPart>> for ModerationInput {
+	fn from(parts: Vec<ContentPart>) -> Self {
+		ModerationInput::Parts(parts)
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Moderation {
+	pub id: String,
+	pub model: String,
+	/// One result per input, or a single result for multimodal inputs.
+	pub results: Vec<ModerationResult>,
+}
+
+impl Moderation {
+	/// Whether any of the inputs was flagged.
+	pub fn flagged(&self) -> bool {
+		self.results.iter().any(|result| result.flagged)
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModerationResult {
+	/// Whether any of the categories is flagged.
+	pub flagged: bool,
+	/// Whether each category is flagged.
+	pub categories: ModerationCategories<bool>,
+	/// The score of each category, between 0 and 1.
+	pub category_scores: ModerationCategories<f64>,
+	/// The input types, `text` and/or `image`, each category was flagged for.
+	/// Only returned by the omni moderation models.
+	pub category_applied_input_types: Option<Json>,
+}
+
+impl ModerationResult {
+	/// The names of the flagged categories, e.g. `harassment/threatening`.
+	pub fn flagged_categories(&self) -> Vec<&'static str> {
+		self.categories.iter().filter(|(_, flagged)| *flagged).map(|(name, _)| name).collect()
+	}
+}
+
+/// The moderation categories, holding either whether each one is flagged or its score.
+///
+/// `illicit` and `illicit/violent` are only returned by the omni moderation models,
+/// and default to `false` and 0 otherwise.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ModerationCategories<T> {
+	#[serde(default)]
+	pub harassment: T,
+	#[serde(default, rename = "harassment/threatening")]
+	pub harassment_threatening: T,
+	#[serde(default)]
+	pub hate: T,
+	#[serde(default, rename = "hate/threatening")]
+	pub hate_threatening: T,
+	#[serde(default)]
+	pub illicit: T,
+	#[serde(default, rename = "illicit/violent")]
+	pub illicit_violent: T,
+	#[serde(default, rename = "self-harm")]
+	pub self_harm: T,
+	#[serde(default, rename = "self-harm/intent")]
+	pub self_harm_intent: T,
+	#[serde(default, rename = "self-harm/instructions")]
+	pub self_harm_instructions: T,
+	#[serde(default)]
+	pub sexual: T,
+	#[serde(default, rename = "sexual/minors")]
+	pub sexual_minors: T,
+	#[serde(default)]
+	pub violence: T,
+	#[serde(default, rename = "violence/graphic")]
+	pub violence_graphic: T,
+}
+
+impl<T: Copy> ModerationCategories<T> {
+	/// The categories with their API names.
+	pub fn iter(&self) -> impl Iterator<Item = (&'static str, T)> {
+		[
+			("harassment", self.harassment),
+			("harassment/threatening", self.harassment_threatening),
+			("hate", self.hate),
+			("hate/threatening", self.hate_threatening),
+			("illicit", self.illicit),
+			("illicit/violent", self.illicit_violent),
+			("self-harm", self.self_harm),
+			("self-harm/intent", self.self_harm_intent),
+			("self-harm/instructions", self.self_harm_instructions),
+			("sexual", self.sexual),
+			("sexual/minors", self.sexual_minors),
+			("violence", self.violence),
+			("violence/graphic", self.violence_graphic),
+		]
+		.into_iter()
+	}
+}
+
+pub trait ModerationsApi {
+	/// Classifies if text and/or image inputs are potentially harmful.
+	fn moderations_create(&self, moderation_body: &ModerationBody) -> ApiResult<Moderation>;
+}
+
+impl ModerationsApi for OpenAI {
+	fn moderations_create(&self, moderation_body: &ModerationBody) -> ApiResult<Moderation> {
+		let request_body = to_json(moderation_body)?;
+		let res = self.post(MODERATIONS_CREATE, request_body)?;
+		let moderation: Moderation = from_json(&res)?;
+		Ok(moderation)
+	}
+}
+
+/// The async counterpart of [`ModerationsApi`].
+#[cfg(feature = "async")]
+pub trait AsyncModerationsApi {
+	/// Classifies if text and/or image inputs are potentially harmful.
+	fn moderations_create(
+		&self,
+		moderation_body: &ModerationBody,
+	) -> impl Future<Output = ApiResult<Moderation>> + Send;
+}
+
+#[cfg(feature = "async")]
+impl AsyncModerationsApi for AsyncOpenAI {
+	async fn moderations_create(&self, moderation_body: &ModerationBody) -> ApiResult<Moderation> {
+		let request_body = to_json(moderation_body)?;
+		let res = self.post(MODERATIONS_CREATE, request_body).await?;
+		let moderation: Moderation = from_json(&res)?;
+		Ok(moderation)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use serde_json::json;
+
+	use crate::{
+		apis::moderations::{ModerationBody, ModerationsApi},
+		openai::new_mock_openai,
+		ContentPart, MockResponse,
+	};
+
+	#[test]
+	fn test_moderations_create() {
+		let (openai, transport) = new_mock_openai();
+		transport.push(MockResponse::json(
+			200,
+			json!({
+				"id": "modr-0d9740456c391e43c445bf0f010940c7",
+				"model": "omni-moderation-latest",
+				"results": [{
+					"flagged": true,
+					"categories": {
+						"harassment": true,
+						"harassment/threatening": true,
+						"sexual": false,
+						"hate": false,
+						"hate/threatening": false,
+						"illicit": false,
+						"illicit/violent": false,
+						"self-harm/intent": false,
+						"self-harm/instructions": false,
+						"self-harm": false,
+						"sexual/minors": false,
+						"violence": true,
+						"violence/graphic": false
+					},
+					"category_scores": {
+						"harassment": 0.8189693396524255,
+						"harassment/threatening": 0.804985420696006,
+						"violence": 0.9
+					},
+					"category_applied_input_types": { "harassment": ["text"] }
+				}]
+			}),
+		));
+		let body = ModerationBody {
+			model: Some("omni-moderation-latest".to_string()),
+			..ModerationBody::new(vec![
+				ContentPart::text("...text to classify goes here..."),
+				ContentPart::image_url("https://example.com/image.png", None),
+			])
+		};
+		let moderation = openai.moderations_create(&body).unwrap();
+		assert!(moderation.flagged());
+		let result = &moderation.results[0];
+		assert_eq!(
+			result.flagged_categories(),
+			["harassment", "harassment/threatening", "violence"]
+		);
+		assert_eq!(result.category_scores.violence, 0.9);
+		assert_eq!(result.category_scores.hate, 0.0);
+
+		assert_eq!(
+			transport.requests()[0].json().unwrap(),
+			json!({
+				"input": [
+					{ "type": "text", "text": "...text to classify goes here..." },
+					{ "type": "image_url", "image_url": { "url": "https://example.com/image.png" } }
+				],
+				"model": "omni-moderation-latest"
+			})
+		);
+	}
+
+	#[test]
+	fn test_moderation_input() {
+		let body = serde_json::to_value(ModerationBody::new("hello")).unwrap();
+		assert_eq!(body, json!({ "input": "hello" }));
+		let body = ModerationBody::new(vec!["a".to_string(), "b".to_string()]);
+		assert_eq!(serde_json::to_value(body).unwrap(), json!({ "input": ["a", "b"] }));
+	}
+}
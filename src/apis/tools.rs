@@ -0,0 +1,245 @@
+// Let the model call Rust functions until it produces a final answer.
+// See: https://platform.openai.com/docs/guides/function-calling
+
+//! Tool registry
+
+use std::fmt::{self, Debug, Display, Formatter};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::*;
+
+use super::{
+	chat::{ChatApi, ChatBody, Tool},
+	completions::Completion,
+};
+
+type Handler = Box<dyn Fn(&FunctionCall) -> Result<String, String> + Send + Sync>;
+
+/// A set of Rust functions exposed to the model as tools.
+///
+/// [`ToolRegistry::run`] sends the registered tools with a chat request,
+/// executes the tool calls returned by the model, sends back their results
+/// and repeats until the model produces a final answer.
+pub struct ToolRegistry {
+	tools: Vec<(Tool, Handler)>,
+	max_iterations: usize,
+}
+
+impl Debug for ToolRegistry {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		f.debug_struct("ToolRegistry")
+			.field("tools", &self.tools.iter().map(|(tool, _)| tool).collect::<Vec<_>>())
+			.field("max_iterations", &self.max_iterations)
+			.finish()
+	}
+}
+
+impl Default for ToolRegistry {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl ToolRegistry {
+	pub fn new() -> ToolRegistry {
+		ToolRegistry { tools: vec![], max_iterations: 10 }
+	}
+
+	/// Registers a function tool.
+	///
+	/// `parameters` is the JSON Schema of the arguments, which are deserialized into `T`
+	/// before calling `handler`. The value returned by `handler` is sent back to the model
+	/// serialized as JSON. Errors, as well as arguments that fail to deserialize,
+	/// are sent back to the model as `{"error": "..."}` so it can correct itself.
+	pub fn register<T, R, E, F>(
+		mut self,
+		name: &str,
+		description: &str,
+		parameters: Json,
+		handler: F,
+	) -> ToolRegistry
+	where
+		T: DeserializeOwned,
+		R: Serialize,
+		E: Display,
+		F: Fn(T) -> Result<R, E> + Send + Sync + 'static,
+	{
+		let handler = move |function: &FunctionCall| {
+			let args = function.parse_arguments::<T>().map_err(|e| e.to_string())?;
+			let output = handler(args).map_err(|e| e.to_string())?;
+			serde_json::to_string(&output).map_err(|e| e.to_string())
+		};
+		self.tools.retain(|(tool, _)| tool.function.name != name);
+		self.tools.push((Tool::function(name, description, parameters), Box::new(handler)));
+		self
+	}
+
+	/// The maximum number of chat completions [`ToolRegistry::run`] will create
+	/// before giving up on a final answer.
+	/// Defaults to 10
+	pub fn set_max_iterations(mut self, max_iterations: usize) -> ToolRegistry {
+		self.max_iterations = max_iterations;
+		self
+	}
+
+	/// The registered tools, as sent in [`ChatBody::tools`].
+	pub fn tools(&self) -> Vec<Tool> {
+		self.tools.iter().map(|(tool, _)| tool.clone()).collect()
+	}
+
+	/// Executes a tool call, returning the `tool` message with its result.
+	pub fn call(&self, tool_call: &ToolCall) -> Message {
+		let name = &tool_call.function.name;
+		let output = match self.tools.iter().find(|(tool, _)| &tool.function.name == name) {
+			Some((_, handler)) => handler(&tool_call.function),
+			None => Err(format!("Unknown tool: {name}")),
+		};
+		let content = output.unwrap_or_else(|e| serde_json::json!({ "error": e }).to_string());
+		Message::tool(&tool_call.id, &content)
+	}
+
+	/// Runs the conversation in `chat_body` until the model answers without calling a tool.
+	///
+	/// The registered tools replace `chat_body.tools`. The assistant messages and
+	/// tool results are appended to `chat_body.messages` as the conversation goes,
+	/// so the same body can be used to continue it. Only the first choice is followed.
+	pub fn run<A: ChatApi + ?Sized>(
+		&self,
+		api: &A,
+		chat_body: &mut ChatBody,
+	) -> ApiResult<Completion> {
+		chat_body.tools = Some(self.tools());
+		for _ in 0..self.max_iterations {
+			let completion = api.chat_completion_create(chat_body)?;
+			let message = match completion.choices.first().and_then(|c| c.message.clone()) {
+				Some(message) => message,
+				None => return Err(Error::ParseError("No message in completion".to_string())),
+			};
+			let tool_calls = message.tool_calls.clone().unwrap_or_default();
+			chat_body.messages.push(message);
+			if tool_calls.is_empty() {
+				return Ok(completion);
+			}
+			for tool_call in &tool_calls {
+				chat_body.messages.push(self.call(tool_call));
+			}
+		}
+		Err(Error::ToolError(format!("No final answer after {} iterations", self.max_iterations)))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use serde::Deserialize;
+	use serde_json::json;
+
+	use crate::{
+		apis::{chat::ChatBody, tools::ToolRegistry},
+		openai::new_mock_openai,
+		Error, Message, MockResponse, Role,
+	};
+
+	#[derive(Deserialize)]
+	struct Weather {
+		city: String,
+	}
+
+	fn weather_registry() -> ToolRegistry {
+		ToolRegistry::new().register(
+			"get_weather",
+			"Get the current weather in a given city",
+			json!({
+				"type": "object",
+				"properties": { "city": { "type": "string" } },
+				"required": ["city"],
+			}),
+			|args: Weather| match args.city.as_str() {
+				"Paris" => Ok("Sunny, 22°C"),
+				_ => Err("Unknown city"),
+			},
+		)
+	}
+
+	/// A chat completion response with a single choice.
+	fn response(message: serde_json::Value, finish_reason: &str) -> MockResponse {
+		MockResponse::json(
+			200,
+			json!({
+				"id": "chatcmpl-123",
+				"object": "chat.completion",
+				"created": 1677652288,
+				"model": "gpt-3.5-turbo-0125",
+				"choices": [{ "index": 0, "message": message, "finish_reason": finish_reason }],
+				"usage": { "prompt_tokens": 60, "completion_tokens": 15, "total_tokens": 75 }
+			}),
+		)
+	}
+
+	fn tool_call_response(id: &str, city: &str) -> MockResponse {
+		response(
+			json!({
+				"role": "assistant",
+				"content": null,
+				"tool_calls": [{
+					"id": id,
+					"type": "function",
+					"function": { "name": "get_weather", "arguments": json!({ "city": city }).to_string() },
+				}],
+			}),
+			"tool_calls",
+		)
+	}
+
+	#[test]
+	fn test_run_tools() {
+		let (openai, transport) = new_mock_openai();
+		transport
+			.push(tool_call_response("call_1", "Paris"))
+			.push(tool_call_response("call_2", "Atlantis"))
+			.push(response(
+				json!({ "role": "assistant", "content": "It is sunny in Paris." }),
+				"stop",
+			));
+		let mut body = ChatBody {
+			model: "gpt-3.5-turbo".to_string(),
+			messages: vec![Message::new(Role::User, "What's the weather like in Paris?")],
+			..Default::default()
+		};
+		let rs = weather_registry().run(&openai, &mut body).unwrap();
+		let answer = rs.choices[0].message.as_ref().unwrap().text();
+		assert_eq!(answer, Some("It is sunny in Paris."));
+
+		assert_eq!(body.messages.len(), 6);
+		assert_eq!(body.messages[2].tool_call_id.as_deref(), Some("call_1"));
+		assert_eq!(body.messages[2].text(), Some("\"Sunny, 22°C\""));
+		assert_eq!(body.messages[4].text(), Some("{\"error\":\"Unknown city\"}"));
+
+		let requests = transport.requests();
+		assert_eq!(requests.len(), 3);
+		let first = requests[0].json().unwrap();
+		assert_eq!(first["tools"][0]["function"]["name"], "get_weather");
+		let messages = &requests[1].json().unwrap()["messages"];
+		assert_eq!(messages[1]["tool_calls"][0]["id"], "call_1");
+		assert_eq!(
+			messages[2],
+			json!({ "role": "tool", "content": "\"Sunny, 22°C\"", "tool_call_id": "call_1" })
+		);
+	}
+
+	#[test]
+	fn test_run_tools_max_iterations() {
+		let (openai, transport) = new_mock_openai();
+		transport
+			.push(tool_call_response("call_1", "Paris"))
+			.push(tool_call_response("call_2", "Paris"));
+		let mut body = ChatBody {
+			model: "gpt-3.5-turbo".to_string(),
+			messages: vec![Message::new(Role::User, "What's the weather like in Paris?")],
+			..Default::default()
+		};
+		let rs = weather_registry().set_max_iterations(2).run(&openai, &mut body);
+		assert!(matches!(rs, Err(Error::ToolError(_))));
+		assert_eq!(transport.requests().len(), 2);
+	}
+}
@@ -0,0 +1,330 @@
+use crate::mpart::Mpart as Multipart;
+use crate::openai::AsyncOpenAI;
+use crate::requests::{api_error, model, new_request, to_bytes, Client};
+use crate::transport::{AsyncBody, AsyncHttpResponse};
+use crate::*;
+
+#[cfg(not(test))]
+use log::{debug, error, info, warn};
+
+#[cfg(test)]
+use std::{eprintln as error, eprintln as warn, println as info, println as debug};
+
+/// The async counterpart of [`Requests`](crate::requests::Requests).
+///
+/// Multipart bodies are sent as [`FormData`], encoded in memory beforehand
+/// since the file streams they hold are read synchronously.
+pub trait AsyncRequests {
+	async fn post(&self, sub_url: &str, body: Json) -> ApiResult<Json>;
+	async fn post_stream(&self, sub_url: &str, body: Json) -> ApiResult<Box<dyn AsyncBody>>;
+	/// Posts a JSON body and returns the raw response body, e.g. generated audio.
+	async fn post_raw(&self, sub_url: &str, body: Json) -> ApiResult<Box<dyn AsyncBody>>;
+	async fn post_multipart(&self, sub_url: &str, form_data: FormData) -> ApiResult<Json>;
+	/// Posts a multipart form and returns the raw response body, e.g. a transcript as SRT.
+	async fn post_multipart_raw(
+		&self,
+		sub_url: &str,
+		form_data: FormData,
+	) -> ApiResult<Box<dyn AsyncBody>>;
+	async fn get(&self, sub_url: &str) -> ApiResult<Json>;
+	/// Gets a raw body, e.g. the content of a file.
+	async fn get_stream(&self, sub_url: &str) -> ApiResult<Box<dyn AsyncBody>>;
+	async fn delete(&self, sub_url: &str) -> ApiResult<Json>;
+}
+
+impl AsyncRequests for AsyncOpenAI {
+	async fn post(&self, sub_url: &str, body: Json) -> ApiResult<Json> {
+		info!("===> 🚀\n\tPost api: {sub_url}, body: {body}");
+
+		let mut request = self.request("POST", sub_url, model(&body))?;
+		request.headers.push(("Content-Type".to_string(), "application/json".to_string()));
+		request.body = Some(to_bytes(&body)?);
+		let response = self.send(sub_url, request).await;
+
+		deal_response(response, sub_url).await
+	}
+
+	async fn post_stream(&self, sub_url: &str, body: Json) -> ApiResult<Box<dyn AsyncBody>> {
+		info!("===> 🚀\n\tPost stream api: {sub_url}, body: {body}");
+
+		let mut request = self.request("POST", sub_url, model(&body))?;
+		request.headers.push(("Content-Type".to_string(), "application/json".to_string()));
+		request.headers.push(("Accept".to_string(), "text/event-stream".to_string()));
+		request.body = Some(to_bytes(&body)?);
+		let response = self.send(sub_url, request).await?;
+
+		debug!("<== ✔️\n\tStreaming api: {sub_url}");
+		Ok(response.body)
+	}
+
+	async fn post_raw(&self, sub_url: &str, body: Json) -> ApiResult<Box<dyn AsyncBody>> {
+		info!("===> 🚀\n\tPost raw api: {sub_url}, body: {body}");
+
+		let mut request = self.request("POST", sub_url, model(&body))?;
+		request.headers.push(("Content-Type".to_string(), "application/json".to_string()));
+		request.body = Some(to_bytes(&body)?);
+		let response = self.send(sub_url, request).await?;
+
+		debug!("<== ✔️\n\tRaw api: {sub_url}");
+		Ok(response.body)
+	}
+
+	async fn post_multipart(&self, sub_url: &str, form_data: FormData) -> ApiResult<Json> {
+		info!("===> 🚀\n\tPost multipart api: {sub_url}, {} bytes", form_data.body.len());
+
+		let request = self.multipart_request(sub_url, form_data)?;
+		let response = self.send(sub_url, request).await;
+
+		deal_response(response, sub_url).await
+	}
+
+	async fn post_multipart_raw(
+		&self,
+		sub_url: &str,
+		form_data: FormData,
+	) -> ApiResult<Box<dyn AsyncBody>> {
+		info!("===> 🚀\n\tPost multipart raw api: {sub_url}, {} bytes", form_data.body.len());
+
+		let request = self.multipart_request(sub_url, form_data)?;
+		let response = self.send(sub_url, request).await?;
+
+		debug!("<== ✔️\n\tRaw api: {sub_url}");
+		Ok(response.body)
+	}
+
+	async fn get(&self, sub_url: &str) -> ApiResult<Json> {
+		info!("===> 🚀\n\tGet api: {sub_url}");
+
+		let mut request = self.request("GET", sub_url, None)?;
+		request.headers.push(("Content-Type".to_string(), "application/json".to_string()));
+		let response = self.send(sub_url, request).await;
+
+		deal_response(response, sub_url).await
+	}
+
+	async fn get_stream(&self, sub_url: &str) -> ApiResult<Box<dyn AsyncBody>> {
+		info!("===> 🚀\n\tGet stream api: {sub_url}");
+
+		let request = self.request("GET", sub_url, None)?;
+		let response = self.send(sub_url, request).await?;
+
+		debug!("<== ✔️\n\tStreaming api: {sub_url}");
+		Ok(response.body)
+	}
+
+	async fn delete(&self, sub_url: &str) -> ApiResult<Json> {
+		info!("===> 🚀\n\tDelete api: {sub_url}");
+
+		let request = self.request("DELETE", sub_url, None)?;
+		let response = self.send(sub_url, request).await;
+
+		deal_response(response, sub_url).await
+	}
+}
+
+impl AsyncOpenAI {
+	/// Prepares a request, see [`new_request`].
+	fn request(&self, method: &str, sub_url: &str, model: Option<&str>) -> ApiResult<HttpRequest> {
+		let client = Client {
+			auth: &self.auth,
+			api_url: &self.api_url,
+			azure: self.azure.as_ref(),
+			headers: &self.headers,
+		};
+		new_request(&client, method, sub_url, model)
+	}
+
+	fn multipart_request(&self, sub_url: &str, form_data: FormData) -> ApiResult<HttpRequest> {
+		let mut request = self.request("POST", sub_url, form_data.model.as_deref())?;
+		request.headers.push(("Content-Type".to_string(), form_data.content_type));
+		request.body = Some(form_data.body);
+		Ok(request)
+	}
+
+	/// Sends a request until it succeeds or the retry policy gives up.
+	async fn send(&self, sub_url: &str, request: HttpRequest) -> ApiResult<AsyncHttpResponse> {
+		let mut attempt = 1;
+		loop {
+			let (err, transport) = match self.transport.send(request.clone()).await {
+				Ok(resp) if resp.status < 400 => return Ok(resp),
+				Ok(resp) => (deal_error(resp, sub_url).await, false),
+				Err(e) => {
+					error!("<== ❌\n\tError api: {sub_url}, error: {:?}", e.message);
+					(Error::RequestError(e.message), e.retryable)
+				},
+			};
+			match self.retry_policy.retry_delay(attempt, &err, transport) {
+				Some(delay) => {
+					warn!("<== 🔁\n\tRetry api: {sub_url} in {delay:?}, attempt: {attempt}");
+					tokio::time::sleep(delay).await;
+					attempt += 1;
+				},
+				None => return Err(err),
+			}
+		}
+	}
+}
+
+/// A multipart form encoded in memory, see `Mpart::into_bytes`.
+pub(crate) struct FormData {
+	content_type: String,
+	body: Vec<u8>,
+	/// The model field, which selects the deployment on Azure OpenAI.
+	model: Option<String>,
+}
+
+impl FormData {
+	pub(crate) fn new(multipart: Multipart) -> ApiResult<FormData> {
+		let model = multipart.text("model").map(str::to_string);
+		let (content_type, body) = multipart.into_bytes()?;
+		Ok(FormData { content_type, body, model })
+	}
+}
+
+async fn deal_response(response: ApiResult<AsyncHttpResponse>, sub_url: &str) -> ApiResult<Json> {
+	let body = read_text(response?.body.as_mut()).await?;
+	let json = serde_json::from_str::<Json>(&body).map_err(|e| Error::decode(e, &body))?;
+	debug!("<== ✔️\n\tDone api: {sub_url}, resp: {json}");
+	Ok(json)
+}
+
+async fn deal_error(mut response: AsyncHttpResponse, sub_url: &str) -> Error {
+	match read_text(response.body.as_mut()).await {
+		Ok(body) => api_error(response.status, response.headers, body, sub_url),
+		Err(e) => e,
+	}
+}
+
+/// Reads a whole body as text, e.g. a transcript.
+pub(crate) async fn read_text(body: &mut dyn AsyncBody) -> ApiResult<String> {
+	let mut bytes = Vec::new();
+	while let Some(chunk) = body.chunk().await? {
+		bytes.extend_from_slice(&chunk);
+	}
+	Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::openai;
+	use serde_json::json;
+
+	#[tokio::test]
+	async fn test_post() {
+		let (openai, transport) = openai::new_mock_async_openai();
+		transport.push(MockResponse::json(
+			200,
+			json!({
+				"id": "chatcmpl-123",
+				"object": "chat.completion",
+				"created": 1677652288,
+				"model": "gpt-3.5-turbo-0125",
+				"choices": [{
+					"index": 0,
+					"message": { "role": "assistant", "content": "This is a test!" },
+					"finish_reason": "stop"
+				}]
+			}),
+		));
+		let body = json!({
+			"model": "gpt-3.5-turbo",
+			"messages": [{"role": "user", "content": "Say this is a test!"}],
+			"temperature": 0.7
+		});
+		let sub_url = "chat/completions";
+		let result = openai.post(sub_url, body.clone()).await.unwrap();
+		assert!(result.to_string().contains("This is a test"));
+
+		let request = &transport.requests()[0];
+		assert_eq!(request.url, "https://api.openai.com/v1/chat/completions");
+		assert_eq!(request.header("authorization"), Some("Bearer sk-test"));
+		assert_eq!(request.json(), Some(body));
+	}
+
+	#[tokio::test]
+	async fn test_get() {
+		let (openai, transport) = openai::new_mock_async_openai();
+		transport.push(MockResponse::json(
+			200,
+			json!({ "object": "list", "data": [{ "id": "babbage-002", "object": "model" }] }),
+		));
+		let resp = openai.get("models").await.unwrap();
+		assert!(resp.to_string().contains("babbage-002"));
+	}
+
+	#[tokio::test]
+	async fn test_error() {
+		let (openai, transport) = openai::new_mock_async_openai();
+		transport.push(MockResponse::json(
+			400,
+			json!({ "error": { "message": "Invalid model", "type": "invalid_request_error" } }),
+		));
+		match openai.get("models").await {
+			Err(Error::ApiError(err)) => {
+				assert_eq!(err.status, Some(400));
+				assert_eq!(err.message, "Invalid model");
+			},
+			other => panic!("unexpected result: {other:?}"),
+		}
+		assert!(matches!(openai.get("models").await, Err(Error::RequestError(_))));
+	}
+
+	#[tokio::test]
+	async fn test_azure() {
+		let (openai, transport) = openai::new_mock_async_openai();
+		let azure = AzureConfig::new("2024-10-21").deployment("gpt-4o", "my-gpt-4o");
+		let openai = openai.set_azure(azure);
+		transport
+			.push(MockResponse::json(200, json!({ "object": "chat.completion" })))
+			.push(MockResponse::json(200, json!({ "text": "Hello" })));
+		openai.post("chat/completions", json!({ "model": "gpt-4o" })).await.unwrap();
+		let mut multipart = Multipart::new();
+		multipart.add_text("model", "whisper-1");
+		let form_data = FormData::new(multipart).unwrap();
+		openai.post_multipart("audio/transcriptions", form_data).await.unwrap();
+
+		let requests = transport.requests();
+		assert_eq!(
+			requests[0].url,
+			"https://api.openai.com/v1/openai/deployments/my-gpt-4o/chat/completions\
+			?api-version=2024-10-21"
+		);
+		assert_eq!(requests[0].header("api-key"), Some("sk-test"));
+		assert_eq!(requests[0].header("authorization"), None);
+		assert_eq!(
+			requests[1].url,
+			"https://api.openai.com/v1/openai/deployments/whisper-1/audio/transcriptions\
+			?api-version=2024-10-21"
+		);
+	}
+
+	#[tokio::test]
+	async fn test_retry() {
+		let (openai, transport) = openai::new_mock_async_openai();
+		let openai = openai.set_retry_policy(RetryPolicy {
+			base_delay: std::time::Duration::from_millis(1),
+			..Default::default()
+		});
+		let rate_limited =
+			|| MockResponse::json(429, json!({ "error": { "message": "Slow down" } }));
+		let reset = TransportError { message: "Connection reset".to_string(), retryable: true };
+		transport
+			.push(rate_limited())
+			.push_error(reset)
+			.push(MockResponse::json(200, json!({ "ok": true })));
+		let resp = openai.post("chat/completions", json!({ "model": "gpt-4o" })).await.unwrap();
+		assert_eq!(resp, json!({ "ok": true }));
+		let requests = transport.requests();
+		assert_eq!(requests.len(), 3);
+		assert!(requests.iter().all(|r| r.json() == Some(json!({ "model": "gpt-4o" }))));
+
+		let openai = openai.set_retry_policy(RetryPolicy::none());
+		transport.push(rate_limited()).push(MockResponse::json(200, json!({ "ok": true })));
+		match openai.get("models").await {
+			Err(Error::ApiError(err)) => assert!(err.is_rate_limited()),
+			other => panic!("unexpected result: {other:?}"),
+		}
+		assert_eq!(transport.requests().len(), 4);
+	}
+}
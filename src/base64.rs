@@ -0,0 +1,61 @@
+//! Standard base64 (RFC 4648) with padding, for data URIs and binary bodies.
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub fn encode(data: &[u8]) -> String {
+	let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+	for chunk in data.chunks(3) {
+		let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+		let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+		for i in 0..4 {
+			if i <= chunk.len() {
+				out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+			} else {
+				out.push('=');
+			}
+		}
+	}
+	out
+}
+
+/// Decodes base64, ignoring whitespace and padding. Returns `None` for invalid characters.
+pub fn decode(data: &str) -> Option<Vec<u8>> {
+	let mut out = Vec::with_capacity(data.len() / 4 * 3);
+	let mut n = 0u32;
+	let mut bits = 0;
+	for c in data.bytes().filter(|c| !c.is_ascii_whitespace() && *c != b'=') {
+		let value = ALPHABET.iter().position(|&a| a == c)? as u32;
+		n = n << 6 | value;
+		bits += 6;
+		if bits >= 8 {
+			bits -= 8;
+			out.push((n >> bits) as u8);
+			n &= (1 << bits) - 1;
+		}
+	}
+	Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{decode, encode};
+
+	#[test]
+	fn test_encode() {
+		assert_eq!(encode(b""), "");
+		assert_eq!(encode(b"f"), "Zg==");
+		assert_eq!(encode(b"fo"), "Zm8=");
+		assert_eq!(encode(b"foo"), "Zm9v");
+		assert_eq!(encode(b"foobar"), "Zm9vYmFy");
+		assert_eq!(encode(&[0xff, 0xfe, 0x00]), "//4A");
+	}
+
+	#[test]
+	fn test_decode() {
+		for data in [&b""[..], b"f", b"fo", b"foo", b"foobar", &[0xff, 0xfe, 0x00]] {
+			assert_eq!(decode(&encode(data)).unwrap(), data);
+		}
+		assert_eq!(decode("Zm9v\nYmFy").unwrap(), b"foobar");
+		assert_eq!(decode("Zm9v!"), None);
+	}
+}
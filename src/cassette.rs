@@ -0,0 +1,322 @@
+//! Recording and replaying requests
+//!
+//! A cassette is a JSON-lines file, each line holding a request and its response.
+//! [`CassetteTransport::record`] sends the requests through another transport and
+//! writes them to the cassette, [`CassetteTransport::replay`] answers them from the
+//! cassette without network, failing for requests which were not recorded.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::transport::{HttpRequest, HttpResponse, Transport, TransportError};
+use crate::{base64, ApiResult, Error, Json};
+
+/// The request headers whose value is replaced by `REDACTED` in cassettes.
+const REDACTED_HEADERS: &[&str] = &["authorization", "api-key", "openai-organization"];
+/// The response headers whose value is replaced by `REDACTED` in cassettes,
+/// as they identify the account.
+const REDACTED_RESPONSE_HEADERS: &[&str] = &["openai-organization", "openai-project"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Interaction {
+	request: RecordedRequest,
+	response: RecordedResponse,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RecordedRequest {
+	method: String,
+	url: String,
+	headers: Vec<(String, String)>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	body: Option<Body>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RecordedResponse {
+	status: u16,
+	/// Sorted, so that recording again gives a readable diff.
+	headers: BTreeMap<String, String>,
+	body: Body,
+}
+
+/// A body, kept readable in the cassette when it is JSON or text.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Body {
+	Json(Json),
+	Text(String),
+	Base64(String),
+}
+
+impl Body {
+	fn new(bytes: &[u8]) -> Body {
+		match std::str::from_utf8(bytes) {
+			Ok(text) => match serde_json::from_str(text) {
+				Ok(json) => Body::Json(json),
+				Err(_) => Body::Text(text.to_string()),
+			},
+			Err(_) => Body::Base64(base64::encode(bytes)),
+		}
+	}
+
+	fn into_bytes(self) -> Vec<u8> {
+		match self {
+			Body::Json(json) => json.to_string().into_bytes(),
+			Body::Text(text) => text.into_bytes(),
+			Body::Base64(data) => base64::decode(&data).unwrap_or_default(),
+		}
+	}
+
+	/// JSON bodies must be equal, other bodies such as multipart forms
+	/// change with each request.
+	fn matches(&self, other: &Body) -> bool {
+		match (self, other) {
+			(Body::Json(a), Body::Json(b)) => a == b,
+			_ => true,
+		}
+	}
+}
+
+/// A transport recording requests to a cassette, or replaying them from it.
+#[derive(Debug)]
+pub struct CassetteTransport {
+	path: PathBuf,
+	mode: Mode,
+}
+
+#[derive(Debug)]
+enum Mode {
+	Record {
+		inner: Box<dyn Transport>,
+		file: Mutex<File>,
+	},
+	/// The interactions not replayed yet.
+	Replay(Mutex<Vec<Interaction>>),
+}
+
+impl CassetteTransport {
+	/// Sends the requests through `inner` and writes them to a new cassette at `path`.
+	///
+	/// The `Authorization`, `api-key` and `OpenAI-Organization` request headers are redacted,
+	/// as are the `openai-organization` and `openai-project` response headers.
+	/// Response bodies are read whole before being returned, streams included.
+	pub fn record(path: impl AsRef<Path>, inner: impl Transport + 'static) -> ApiResult<Self> {
+		let path = path.as_ref().to_path_buf();
+		if let Some(dir) = path.parent() {
+			fs::create_dir_all(dir)?;
+		}
+		let file = File::create(&path)?;
+		Ok(CassetteTransport {
+			path,
+			mode: Mode::Record { inner: Box::new(inner), file: Mutex::new(file) },
+		})
+	}
+
+	/// Answers the requests from the cassette at `path`, in the order they were recorded.
+	///
+	/// A request matches a recorded one with the same method, url and, for JSON bodies,
+	/// the same body, so changing a prompt makes the replay fail until it is recorded again.
+	/// Other bodies, such as multipart forms, are not compared.
+	pub fn replay(path: impl AsRef<Path>) -> ApiResult<Self> {
+		let path = path.as_ref().to_path_buf();
+		let mut interactions = Vec::new();
+		for line in BufReader::new(File::open(&path)?).lines() {
+			let line = line?;
+			if line.trim().is_empty() {
+				continue;
+			}
+			let interaction = serde_json::from_str(&line).map_err(|e| Error::decode(e, &line))?;
+			interactions.push(interaction);
+		}
+		Ok(CassetteTransport { path, mode: Mode::Replay(Mutex::new(interactions)) })
+	}
+
+	fn record_interaction(
+		&self,
+		inner: &dyn Transport,
+		file: &Mutex<File>,
+		request: HttpRequest,
+	) -> Result<HttpResponse, TransportError> {
+		let recorded = RecordedRequest {
+			method: request.method.clone(),
+			url: request.url.clone(),
+			headers: request
+				.headers
+				.iter()
+				.map(|(name, value)| {
+					if REDACTED_HEADERS.contains(&name.to_lowercase().as_str()) {
+						(name.clone(), "REDACTED".to_string())
+					} else {
+						(name.clone(), value.clone())
+					}
+				})
+				.collect(),
+			// Binary uploads, e.g. audio files, are left out of the cassette.
+			body: request.body.as_deref().map(Body::new).filter(|b| !matches!(b, Body::Base64(_))),
+		};
+		let mut response = inner.send(request)?;
+		let mut body = Vec::new();
+		response.body.read_to_end(&mut body).map_err(|e| self.error(e))?;
+		let mut headers = response.headers;
+		headers.remove("set-cookie");
+		let recorded_headers = headers
+			.iter()
+			.map(|(name, value)| {
+				if REDACTED_RESPONSE_HEADERS.contains(&name.as_str()) {
+					(name.clone(), "REDACTED".to_string())
+				} else {
+					(name.clone(), value.clone())
+				}
+			})
+			.collect();
+		let interaction = Interaction {
+			request: recorded,
+			response: RecordedResponse {
+				status: response.status,
+				headers: recorded_headers,
+				body: Body::new(&body),
+			},
+		};
+		let line = serde_json::to_string(&interaction).map_err(|e| self.error(e))?;
+		let mut file = file.lock().unwrap_or_else(|e| e.into_inner());
+		writeln!(file, "{line}").map_err(|e| self.error(e))?;
+		Ok(HttpResponse { status: response.status, headers, body: Box::new(Cursor::new(body)) })
+	}
+
+	fn replay_interaction(
+		&self,
+		interactions: &Mutex<Vec<Interaction>>,
+		request: HttpRequest,
+	) -> Result<HttpResponse, TransportError> {
+		let body = request.body.as_deref().map(Body::new);
+		let mut interactions = interactions.lock().unwrap_or_else(|e| e.into_inner());
+		let index = interactions
+			.iter()
+			.position(|i| {
+				i.request.method == request.method
+					&& i.request.url == request.url
+					&& match (&i.request.body, &body) {
+						(Some(recorded), Some(body)) => recorded.matches(body),
+						(Some(_), None) => false,
+						(None, _) => true,
+					}
+			})
+			.ok_or_else(|| {
+				self.error(format!("no recorded response for {} {}", request.method, request.url))
+			})?;
+		let response = interactions.remove(index).response;
+		Ok(HttpResponse {
+			status: response.status,
+			headers: response.headers.into_iter().collect(),
+			body: Box::new(Cursor::new(response.body.into_bytes())),
+		})
+	}
+
+	fn error(&self, err: impl std::fmt::Display) -> TransportError {
+		TransportError {
+			message: format!("Cassette {}: {err}", self.path.display()),
+			retryable: false,
+		}
+	}
+}
+
+impl Transport for CassetteTransport {
+	fn send(&self, request: HttpRequest) -> Result<HttpResponse, TransportError> {
+		match &self.mode {
+			Mode::Record { inner, file } => self.record_interaction(inner.as_ref(), file, request),
+			Mode::Replay(interactions) => self.replay_interaction(interactions, request),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::Read;
+
+	use serde_json::json;
+
+	use super::CassetteTransport;
+	use crate::{
+		apis::chat::{ChatApi, ChatBody},
+		transport::{HttpRequest, MockResponse, MockTransport, Transport},
+		Auth, Message, OpenAI, Role,
+	};
+
+	fn request(body: &[u8]) -> HttpRequest {
+		HttpRequest {
+			method: "POST".to_string(),
+			url: "https://api.openai.com/v1/audio/speech".to_string(),
+			headers: vec![("Authorization".to_string(), "Bearer sk-secret".to_string())],
+			body: Some(body.to_vec()),
+		}
+	}
+
+	#[test]
+	fn test_record_replay() {
+		let path = std::env::temp_dir().join(format!("cassette-{}.jsonl", std::process::id()));
+		let inner = MockTransport::new();
+		inner.push(
+			MockResponse::json(200, json!({ "n": 1 }))
+				.header("set-cookie", "id=1")
+				.header("openai-organization", "org-secret")
+				.header("openai-project", "proj_secret"),
+		);
+		inner.push(MockResponse::new(200, vec![0xff, 0xfb, 0x90]));
+
+		let recorder = CassetteTransport::record(&path, inner).unwrap();
+		let response = recorder.send(request(br#"{"input": "a"}"#)).unwrap();
+		assert_eq!(response.headers["openai-organization"], "org-secret");
+		recorder.send(request(br#"{"input": "b"}"#)).unwrap();
+		drop(recorder);
+		let cassette = std::fs::read_to_string(&path).unwrap();
+		assert_eq!(cassette.lines().count(), 2);
+		assert!(!cassette.contains("sk-secret") && !cassette.contains("set-cookie"));
+		assert!(!cassette.contains("org-secret") && !cassette.contains("proj_secret"));
+
+		let player = CassetteTransport::replay(&path).unwrap();
+		let mut body = Vec::new();
+		let mut response = player.send(request(br#"{"input": "b"}"#)).unwrap();
+		response.body.read_to_end(&mut body).unwrap();
+		assert_eq!(body, [0xff, 0xfb, 0x90]);
+		let mut response = player.send(request(br#"{ "input": "a" }"#)).unwrap();
+		body.clear();
+		response.body.read_to_end(&mut body).unwrap();
+		assert_eq!(body, br#"{"n":1}"#);
+		assert!(player.send(request(br#"{"input": "a"}"#)).is_err());
+		assert!(player.send(request(br#"{"input": "c"}"#)).is_err());
+		std::fs::remove_file(path).unwrap();
+	}
+
+	#[test]
+	fn test_replay_cassette_file() {
+		let player =
+			CassetteTransport::replay("test_files/cassettes/chat_completion.jsonl").unwrap();
+		let openai =
+			OpenAI::new(Auth::new("sk-test"), "https://api.openai.com/v1/").set_transport(player);
+		let body = ChatBody {
+			model: "gpt-4o-mini".to_string(),
+			max_tokens: Some(5),
+			messages: vec![Message::new(Role::User, "Say hello")],
+			..Default::default()
+		};
+		let content: String = openai
+			.chat_completion_stream(&body)
+			.unwrap()
+			.map(|chunk| chunk.unwrap().choices[0].delta.content.clone().unwrap_or_default())
+			.collect();
+		assert_eq!(content, "Hello!");
+		let completion = openai.chat_completion_create(&body).unwrap();
+		let message = completion.choices[0].message.as_ref().unwrap();
+		assert_eq!(message.text(), Some("Hello!"));
+		assert_eq!(completion.usage.total_tokens, Some(11));
+
+		let body = ChatBody { messages: vec![Message::new(Role::User, "Say bye")], ..body };
+		assert!(openai.chat_completion_create(&body).is_err());
+	}
+}
@@ -0,0 +1,248 @@
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use std::io;
+
+use crate::Json;
+
+/// The maximum length of the body kept in [`Error::DecodeError`].
+const BODY_SNIPPET_LEN: usize = 1024;
+
+pub type ApiResult<T> = Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+	/// An Error returned by the API
+	ApiError(Box<ApiError>),
+	/// An Error not related to the API
+	RequestError(String),
+	/// The content generated by the model could not be parsed
+	ParseError(String),
+	/// The model did not finish calling tools
+	ToolError(String),
+	/// The model refused to generate the requested structured output
+	Refusal(String),
+	/// The response body could not be decoded
+	DecodeError {
+		message: String,
+		/// The beginning of the raw body
+		body: String,
+	},
+	/// Reading or writing a file or stream failed
+	IoError(io::Error),
+	/// The proxy configuration is invalid
+	InvalidProxy(String),
+	/// An input was rejected before sending it, e.g. a path which is not a file or an upload failing
+	/// [`crate::apis::images::ImageEditBody::validate`]
+	InvalidInput(String),
+}
+
+impl Error {
+	pub(crate) fn decode<E: Display>(err: E, body: &str) -> Error {
+		let body = match body.char_indices().nth(BODY_SNIPPET_LEN) {
+			Some((end, _)) => format!("{}...", &body[..end]),
+			None => body.to_string(),
+		};
+		Error::DecodeError { message: err.to_string(), body }
+	}
+}
+
+impl Display for Error {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		match self {
+			Error::ApiError(err) => write!(f, "API error: {}", err),
+			Error::RequestError(msg) => write!(f, "Request error: {}", msg),
+			Error::ParseError(msg) => write!(f, "Parse error: {}", msg),
+			Error::ToolError(msg) => write!(f, "Tool error: {}", msg),
+			Error::Refusal(msg) => write!(f, "Refusal: {}", msg),
+			Error::DecodeError { message, body } => {
+				write!(f, "Decode error: {}, body: {}", message, body)
+			},
+			Error::IoError(err) => write!(f, "IO error: {}", err),
+			Error::InvalidProxy(msg) => write!(f, "Invalid proxy: {}", msg),
+			Error::InvalidInput(msg) => write!(f, "Invalid input: {}", msg),
+		}
+	}
+}
+
+impl std::error::Error for Error {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			Error::IoError(err) => Some(err),
+			_ => None,
+		}
+	}
+}
+
+impl From<io::Error> for Error {
+	fn from(err: io::Error) -> Self {
+		Error::IoError(err)
+	}
+}
+
+impl From<ApiError> for Error {
+	fn from(err: ApiError) -> Self {
+		Error::ApiError(Box::new(err))
+	}
+}
+
+/// An error returned by the API, see: https://platform.openai.com/docs/guides/error-codes
+#[derive(Debug, Clone)]
+pub struct ApiError {
+	/// The HTTP status of the response.
+	/// `None` for errors sent in the middle of a stream.
+	pub status: Option<u16>,
+	/// A human-readable description of the error.
+	pub message: String,
+	/// The `error.type`, e.g. `invalid_request_error`.
+	pub error_type: Option<String>,
+	/// The `error.code`, e.g. `context_length_exceeded`.
+	pub code: Option<String>,
+	/// The request parameter the error relates to, if any.
+	pub param: Option<String>,
+	/// The `x-request-id` header, to be mentioned when contacting support.
+	pub request_id: Option<String>,
+	/// The response headers, with lowercase names.
+	pub headers: HashMap<String, String>,
+	/// The raw response body.
+	pub body: Json,
+}
+
+impl ApiError {
+	/// Parses the `{"error": {...}}` body of an error response.
+	pub fn new(status: Option<u16>, headers: HashMap<String, String>, body: Json) -> ApiError {
+		let error = body.get("error").unwrap_or(&body);
+		let field = |name: &str| match error.get(name) {
+			Some(Json::String(value)) => Some(value.clone()),
+			Some(Json::Null) | None => None,
+			Some(value) => Some(value.to_string()),
+		};
+		let message = field("message").unwrap_or_else(|| match &body {
+			Json::String(body) => body.clone(),
+			body => body.to_string(),
+		});
+		ApiError {
+			status,
+			message,
+			error_type: field("type"),
+			code: field("code"),
+			param: field("param"),
+			request_id: headers.get("x-request-id").cloned(),
+			headers,
+			body,
+		}
+	}
+
+	/// Too many requests or tokens were sent in a short period of time, the request can be retried.
+	/// Exhausted quotas are reported by [`ApiError::is_quota_exceeded`] instead.
+	pub fn is_rate_limited(&self) -> bool {
+		!self.is_quota_exceeded()
+			&& (self.status == Some(429) || self.code.as_deref() == Some("rate_limit_exceeded"))
+	}
+
+	/// The account ran out of credits or hit its monthly spend limit.
+	pub fn is_quota_exceeded(&self) -> bool {
+		self.code.as_deref() == Some("insufficient_quota")
+			|| self.error_type.as_deref() == Some("insufficient_quota")
+	}
+
+	/// The messages and `max_tokens` exceed the context length of the model.
+	pub fn is_context_length_exceeded(&self) -> bool {
+		self.code.as_deref() == Some("context_length_exceeded")
+	}
+
+	/// The API key or organization is invalid, or lacks permission for the request.
+	pub fn is_auth_error(&self) -> bool {
+		matches!(self.status, Some(401) | Some(403))
+			|| self.code.as_deref() == Some("invalid_api_key")
+	}
+
+	/// The server failed to process the request.
+	pub fn is_server_error(&self) -> bool {
+		matches!(self.status, Some(500..=599)) || self.error_type.as_deref() == Some("server_error")
+	}
+}
+
+impl Display for ApiError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		if let Some(status) = self.status {
+			write!(f, "{} ", status)?;
+		}
+		if let Some(error_type) = &self.error_type {
+			write!(f, "{}: ", error_type)?;
+		}
+		write!(f, "{}", self.message)?;
+		if let Some(request_id) = &self.request_id {
+			write!(f, " (request id: {})", request_id)?;
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::collections::HashMap;
+
+	use serde_json::json;
+
+	use super::{ApiError, Error};
+
+	#[test]
+	fn test_decode_error() {
+		let body = format!("<html>{}</html>", "千".repeat(2000));
+		match Error::decode("expected value at line 1 column 1", &body) {
+			Error::DecodeError { message, body } => {
+				assert_eq!(message, "expected value at line 1 column 1");
+				assert!(body.starts_with("<html>千"));
+				assert_eq!(body.chars().count(), 1024 + 3);
+			},
+			err => panic!("unexpected error: {err}"),
+		}
+	}
+
+	#[test]
+	fn test_api_error() {
+		let headers = HashMap::from([("x-request-id".to_string(), "req_123".to_string())]);
+		let err = ApiError::new(
+			Some(400),
+			headers,
+			json!({
+				"error": {
+					"message": "This model's maximum context length is 4097 tokens.",
+					"type": "invalid_request_error",
+					"param": "messages",
+					"code": "context_length_exceeded",
+				}
+			}),
+		);
+		assert!(err.is_context_length_exceeded());
+		assert!(!err.is_rate_limited());
+		assert!(!err.is_auth_error());
+		assert_eq!(err.param.as_deref(), Some("messages"));
+		assert_eq!(
+			err.to_string(),
+			"400 invalid_request_error: This model's maximum context length is 4097 tokens. \
+			 (request id: req_123)"
+		);
+	}
+
+	#[test]
+	fn test_api_error_kinds() {
+		let error = |status: u16, error_type: &str, code: Option<&str>| {
+			ApiError::new(
+				Some(status),
+				HashMap::new(),
+				json!({ "error": { "message": "", "type": error_type, "code": code } }),
+			)
+		};
+		assert!(error(429, "requests", Some("rate_limit_exceeded")).is_rate_limited());
+		let quota = error(429, "insufficient_quota", Some("insufficient_quota"));
+		assert!(quota.is_quota_exceeded());
+		assert!(!quota.is_rate_limited());
+		assert!(error(401, "invalid_request_error", Some("invalid_api_key")).is_auth_error());
+		assert!(error(503, "server_error", None).is_server_error());
+
+		let unexpected = ApiError::new(Some(502), HashMap::new(), json!("Bad gateway"));
+		assert_eq!(unexpected.message, "Bad gateway");
+		assert!(unexpected.code.is_none());
+	}
+}
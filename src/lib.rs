@@ -7,7 +7,7 @@
 //! [Options](struct.Options.html) struct.
 //!
 //! # Example
-//! ```rust
+//! ```rust,no_run
 //! use openai_api_rust::*;
 //! use openai_api_rust::chat::*;
 //! use openai_api_rust::completions::*;
@@ -29,56 +29,60 @@
 //!         frequency_penalty: None,
 //!         logit_bias: None,
 //!         user: None,
-//!         messages: vec![Message { role: Role::User, content: "Hello!".to_string() }],
+//!         messages: vec![Message::new(Role::User, "Hello!")],
+//!         ..Default::default()
 //!     };
 //!     let rs = openai.chat_completion_create(&body);
 //!     let choice = rs.unwrap().choices;
 //!     let message = &choice[0].message.as_ref().unwrap();
-//!     assert!(message.content.contains("Hello"));
+//!     assert!(message.text().unwrap().contains("Hello"));
 //! }
 //! ```
 //!
 //! ## Use proxy
 //!
-//! ```rust
+//! ```rust,ignore
 //! // Load proxy from env
 //! let openai = OpenAI::new(auth, "https://api.openai.com/v1/")
-//!        .use_env_proxy();
+//!        .use_env_proxy()
+//!        .unwrap();
 //!
 //! // Set the proxy manually
 //! let openai = OpenAI::new(auth, "https://api.openai.com/v1/")
-//!        .set_proxy("http://127.0.0.1:1080");
+//!        .set_proxy("http://127.0.0.1:1080")
+//!        .unwrap();
 //! ```
 
 #![warn(unused_crate_dependencies)]
 
 pub mod apis;
-use std::fmt::{self, Display, Formatter};
 
 pub use apis::*;
+mod error;
+pub use error::*;
 pub mod openai;
 pub use openai::*;
+#[cfg(feature = "async")]
+mod async_requests;
+mod base64;
+pub mod cassette;
+pub use cassette::*;
 mod mpart;
+mod png;
 mod requests;
+pub mod retry;
+pub use retry::*;
+pub mod stream;
+pub use stream::*;
+pub mod transport;
+pub use transport::*;
+mod wav;
+
+/// The TLS library, to build the configuration given to [`OpenAIBuilder::tls_config`].
+pub use rustls;
 
 use log as _;
+#[cfg(test)]
+use tokio as _;
 
 pub type Json = serde_json::Value;
-pub type ApiResult<T> = Result<T, Error>;
-
-#[derive(Debug)]
-pub enum Error {
-	/// An Error returned by the API
-	ApiError(String),
-	/// An Error not related to the API
-	RequestError(String),
-}
-
-impl Display for Error {
-	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-		match self {
-			Error::ApiError(msg) => write!(f, "API error: {}", msg),
-			Error::RequestError(msg) => write!(f, "Request error: {}", msg),
-		}
-	}
-}
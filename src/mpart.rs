@@ -54,9 +54,9 @@ pub struct LazyError<'a, E> {
 }
 
 /// Take `self.error`, discarding `self.field_name`.
-impl<'a> Into<io::Error> for LazyError<'a, io::Error> {
-	fn into(self) -> io::Error {
-		self.error
+impl<'a> From<LazyError<'a, io::Error>> for io::Error {
+	fn from(err: LazyError<'a, io::Error>) -> io::Error {
+		err.error
 	}
 }
 
@@ -123,6 +123,14 @@ impl<'n, 'd> Mpart<'n, 'd> {
 		self
 	}
 
+	/// Returns the value of a text field.
+	pub fn text(&self, name: &str) -> Option<&str> {
+		self.fields.iter().find_map(|field| match &field.data {
+			Data::Text(text) if field.name == name => Some(text.as_ref()),
+			_ => None,
+		})
+	}
+
 	/// Export the multipart data contained in this lazy request as an adaptor which implements `Read`.
 	///
 	/// During this step, if any files were added by path then they will be opened for reading
@@ -130,6 +138,22 @@ impl<'n, 'd> Mpart<'n, 'd> {
 	pub fn prepare(&mut self) -> LazyIoResult<'n, PreparedFields<'d>> {
 		PreparedFields::from_fields(&mut self.fields)
 	}
+
+	/// Returns the request body as a reader, with its `Content-Type` header.
+	pub fn into_reader(mut self) -> io::Result<(String, PreparedFields<'d>)> {
+		let form_data = self.prepare()?;
+		let content_type = format!("multipart/form-data; boundary={}", form_data.boundary());
+		Ok((content_type, form_data))
+	}
+
+	/// Reads the whole request body in memory,
+	/// returning it with its `Content-Type` header.
+	pub fn into_bytes(self) -> io::Result<(String, Vec<u8>)> {
+		let (content_type, mut form_data) = self.into_reader()?;
+		let mut body = Vec::new();
+		form_data.read_to_end(&mut body)?;
+		Ok((content_type, body))
+	}
 }
 
 /// The result of [`Multipart::prepare()`](struct.Multipart.html#method.prepare).
@@ -171,7 +195,7 @@ impl<'d> PreparedFields<'d> {
 						&field.name,
 						&boundary,
 						&stream.content_type,
-						stream.filename.as_ref().map(|f| &**f),
+						stream.filename.as_deref(),
 						stream.stream,
 					));
 				},
@@ -0,0 +1,88 @@
+//! Reading the header of PNG files, to check images before uploading them.
+
+const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+const GRAY_ALPHA: u8 = 4;
+const RGBA: u8 = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PngInfo {
+	pub width: u32,
+	pub height: u32,
+	/// Whether the image has an alpha channel or a transparent color.
+	pub has_alpha: bool,
+}
+
+/// Parses the dimensions and transparency of a PNG file, without decoding the pixels.
+pub fn parse(bytes: &[u8]) -> Result<PngInfo, String> {
+	if !bytes.starts_with(SIGNATURE) {
+		return Err("not a PNG file".to_string());
+	}
+	let mut info = None;
+	let mut rest = &bytes[SIGNATURE.len()..];
+	while rest.len() >= 8 {
+		let size = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+		let id = &rest[4..8];
+		let body = &rest[8..(8 + size).min(rest.len())];
+		match id {
+			b"IHDR" => {
+				if body.len() < 13 {
+					return Err("truncated IHDR chunk".to_string());
+				}
+				let u32_at =
+					|i: usize| u32::from_be_bytes([body[i], body[i + 1], body[i + 2], body[i + 3]]);
+				let color_type = body[9];
+				info = Some(PngInfo {
+					width: u32_at(0),
+					height: u32_at(4),
+					has_alpha: color_type == GRAY_ALPHA || color_type == RGBA,
+				});
+			},
+			// Palette and opaque color types may still declare a transparent color.
+			b"tRNS" => {
+				if let Some(info) = info.as_mut() {
+					info.has_alpha = true;
+				}
+			},
+			// The transparency chunk comes before the image data.
+			b"IDAT" => break,
+			_ => {},
+		}
+		// Chunks end with a 4 bytes CRC.
+		rest = &rest[(8 + size + 4).min(rest.len())..];
+	}
+	info.ok_or("no IHDR chunk".to_string())
+}
+
+#[cfg(test)]
+pub(crate) fn write_header(width: u32, height: u32, color_type: u8) -> Vec<u8> {
+	let mut png = SIGNATURE.to_vec();
+	png.extend_from_slice(&13u32.to_be_bytes());
+	png.extend_from_slice(b"IHDR");
+	png.extend_from_slice(&width.to_be_bytes());
+	png.extend_from_slice(&height.to_be_bytes());
+	png.extend_from_slice(&[8, color_type, 0, 0, 0]);
+	png.extend_from_slice(&[0; 4]);
+	png
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{parse, write_header, PngInfo, RGBA};
+
+	#[test]
+	fn test_parse() {
+		let png = write_header(512, 256, RGBA);
+		assert_eq!(parse(&png).unwrap(), PngInfo { width: 512, height: 256, has_alpha: true });
+
+		// An RGB image with a transparent color.
+		let mut png = write_header(64, 64, 2);
+		png.extend_from_slice(b"\x00\x00\x00\x06tRNS\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00");
+		png.extend_from_slice(b"\x00\x00\x00\x00IDAT\x00\x00\x00\x00");
+		assert!(parse(&png).unwrap().has_alpha);
+		assert!(!parse(&write_header(64, 64, 2)).unwrap().has_alpha);
+
+		assert!(parse(b"\xff\xd8\xff\xe0").is_err());
+		assert!(parse(&png[..20]).is_err());
+	}
+}
@@ -1,110 +1,490 @@
+use std::collections::HashMap;
+use std::io::Read;
+
+use serde::{de::DeserializeOwned, Serialize};
+
 use crate::mpart::Mpart as Multipart;
 
 use crate::openai::OpenAI;
 use crate::*;
 
 #[cfg(not(test))]
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 
 #[cfg(test)]
-use std::{eprintln as error, println as info, println as debug};
+use std::{eprintln as error, eprintln as warn, println as info, println as debug};
 
 pub trait Requests {
 	fn post(&self, sub_url: &str, body: Json) -> ApiResult<Json>;
+	fn post_stream(&self, sub_url: &str, body: Json) -> ApiResult<Box<dyn Read + Send>>;
+	/// Posts a JSON body and returns the raw response body, e.g. generated audio.
+	fn post_raw(&self, sub_url: &str, body: Json) -> ApiResult<Box<dyn Read + Send>>;
 	fn post_multipart(&self, sub_url: &str, multipart: Multipart) -> ApiResult<Json>;
+	/// Posts a multipart form and returns the raw response body, e.g. a transcript as SRT.
+	fn post_multipart_raw(
+		&self,
+		sub_url: &str,
+		multipart: Multipart,
+	) -> ApiResult<Box<dyn Read + Send>>;
 	fn get(&self, sub_url: &str) -> ApiResult<Json>;
+	/// Gets a raw body, e.g. the content of a file.
+	fn get_stream(&self, sub_url: &str) -> ApiResult<Box<dyn Read + Send>>;
+	fn delete(&self, sub_url: &str) -> ApiResult<Json>;
 }
 
 impl Requests for OpenAI {
 	fn post(&self, sub_url: &str, body: Json) -> ApiResult<Json> {
 		info!("===> 🚀\n\tPost api: {sub_url}, body: {body}");
 
-		let response = self
-			.agent
-			.post(&(self.api_url.clone() + sub_url))
-			.set("Content-Type", "application/json")
-			.set("OpenAI-Organization", &self.auth.organization.clone().unwrap_or_default())
-			.set("Authorization", &format!("Bearer {}", self.auth.api_key))
-			.send_json(body);
+		let mut request = self.request("POST", sub_url, model(&body))?;
+		request.headers.push(("Content-Type".to_string(), "application/json".to_string()));
+		request.body = Some(to_bytes(&body)?);
+		let response = self.send(sub_url, request);
 
 		deal_response(response, sub_url)
 	}
 
+	fn post_stream(&self, sub_url: &str, body: Json) -> ApiResult<Box<dyn Read + Send>> {
+		info!("===> 🚀\n\tPost stream api: {sub_url}, body: {body}");
+
+		let mut request = self.request("POST", sub_url, model(&body))?;
+		request.headers.push(("Content-Type".to_string(), "application/json".to_string()));
+		request.headers.push(("Accept".to_string(), "text/event-stream".to_string()));
+		request.body = Some(to_bytes(&body)?);
+		let response = self.send(sub_url, request)?;
+
+		debug!("<== ✔️\n\tStreaming api: {sub_url}");
+		Ok(response.body)
+	}
+
+	fn post_raw(&self, sub_url: &str, body: Json) -> ApiResult<Box<dyn Read + Send>> {
+		info!("===> 🚀\n\tPost raw api: {sub_url}, body: {body}");
+
+		let mut request = self.request("POST", sub_url, model(&body))?;
+		request.headers.push(("Content-Type".to_string(), "application/json".to_string()));
+		request.body = Some(to_bytes(&body)?);
+		let response = self.send(sub_url, request)?;
+
+		debug!("<== ✔️\n\tRaw api: {sub_url}");
+		Ok(response.body)
+	}
+
 	fn get(&self, sub_url: &str) -> ApiResult<Json> {
 		info!("===> 🚀\n\tGet api: {sub_url}");
 
-		let response = self
-			.agent
-			.get(&(self.api_url.clone() + sub_url))
-			.set("Content-Type", "application/json")
-			.set("OpenAI-Organization", &self.auth.organization.clone().unwrap_or_default())
-			.set("Authorization", &format!("Bearer {}", self.auth.api_key))
-			.call();
+		let mut request = self.request("GET", sub_url, None)?;
+		request.headers.push(("Content-Type".to_string(), "application/json".to_string()));
+		let response = self.send(sub_url, request);
 
 		deal_response(response, sub_url)
 	}
 
-	fn post_multipart(&self, sub_url: &str, mut multipart: Multipart) -> ApiResult<Json> {
-		info!("===> 🚀\n\tPost multipart api: {sub_url}, multipart: {:?}", multipart);
+	fn get_stream(&self, sub_url: &str) -> ApiResult<Box<dyn Read + Send>> {
+		info!("===> 🚀\n\tGet stream api: {sub_url}");
 
-		let form_data = multipart.prepare().unwrap();
+		let request = self.request("GET", sub_url, None)?;
+		let response = self.send(sub_url, request)?;
 
-		let response = self
-			.agent
-			.post(&(self.api_url.clone() + sub_url))
-			.set("Content-Type", &format!("multipart/form-data; boundary={}", form_data.boundary()))
-			.set("OpenAI-Organization", &self.auth.organization.clone().unwrap_or_default())
-			.set("Authorization", &format!("Bearer {}", self.auth.api_key))
-			.send(form_data);
+		debug!("<== ✔️\n\tStreaming api: {sub_url}");
+		Ok(response.body)
+	}
+
+	fn delete(&self, sub_url: &str) -> ApiResult<Json> {
+		info!("===> 🚀\n\tDelete api: {sub_url}");
+
+		let request = self.request("DELETE", sub_url, None)?;
+		let response = self.send(sub_url, request);
+
+		deal_response(response, sub_url)
+	}
+
+	fn post_multipart(&self, sub_url: &str, multipart: Multipart) -> ApiResult<Json> {
+		info!("===> 🚀\n\tPost multipart api: {sub_url}, multipart: {:?}", multipart);
+
+		let response = self.send_multipart(sub_url, multipart);
 
 		deal_response(response, sub_url)
 	}
+
+	fn post_multipart_raw(
+		&self,
+		sub_url: &str,
+		multipart: Multipart,
+	) -> ApiResult<Box<dyn Read + Send>> {
+		info!("===> 🚀\n\tPost multipart raw api: {sub_url}, multipart: {:?}", multipart);
+
+		let response = self.send_multipart(sub_url, multipart)?;
+
+		debug!("<== ✔️\n\tRaw api: {sub_url}");
+		Ok(response.body)
+	}
 }
 
-fn deal_response(response: Result<ureq::Response, ureq::Error>, sub_url: &str) -> ApiResult<Json> {
-	match response {
-		Ok(resp) => {
-			let json = resp.into_json::<Json>().unwrap();
-			debug!("<== ✔️\n\tDone api: {sub_url}, resp: {json}");
-			return Ok(json);
-		},
-		Err(err) => match err {
-			ureq::Error::Status(status, response) => {
-				let error_msg = response.into_json::<Json>().unwrap();
-				error!("<== ❌\n\tError api: {sub_url}, status: {status}, error: {error_msg}");
-				return Err(Error::ApiError(format!("{error_msg}")));
-			},
-			ureq::Error::Transport(e) => {
-				error!("<== ❌\n\tError api: {sub_url}, error: {:?}", e.to_string());
-				return Err(Error::RequestError(e.to_string()));
+impl OpenAI {
+	/// Prepares a request, see [`new_request`].
+	fn request(&self, method: &str, sub_url: &str, model: Option<&str>) -> ApiResult<HttpRequest> {
+		let client = Client {
+			auth: &self.auth,
+			api_url: &self.api_url,
+			azure: self.azure.as_ref(),
+			headers: &self.headers,
+		};
+		new_request(&client, method, sub_url, model)
+	}
+
+	/// Sends a multipart form, streaming it when the retry policy allows a single attempt.
+	///
+	/// File streams can only be read once, so with retries enabled the whole form is
+	/// buffered in memory to be sent again, which costs as much memory as the uploaded files.
+	fn send_multipart(&self, sub_url: &str, multipart: Multipart) -> ApiResult<HttpResponse> {
+		let mut request = self.request("POST", sub_url, multipart.text("model"))?;
+		if self.retry_policy.max_attempts > 1 {
+			let (content_type, form_data) = multipart.into_bytes()?;
+			request.headers.push(("Content-Type".to_string(), content_type));
+			request.body = Some(form_data);
+			return self.send(sub_url, request);
+		}
+		let (content_type, mut form_data) = multipart.into_reader()?;
+		request.headers.push(("Content-Type".to_string(), content_type));
+		match self.transport.send_reader(request, &mut form_data) {
+			Ok(resp) if resp.status < 400 => Ok(resp),
+			Ok(resp) => Err(deal_error(resp, sub_url)),
+			Err(e) => {
+				error!("<== ❌\n\tError api: {sub_url}, error: {:?}", e.message);
+				Err(Error::RequestError(e.message))
 			},
-		},
+		}
 	}
+
+	/// Sends a request until it succeeds or the retry policy gives up.
+	fn send(&self, sub_url: &str, request: HttpRequest) -> ApiResult<HttpResponse> {
+		let mut attempt = 1;
+		loop {
+			let (err, transport) = match self.transport.send(request.clone()) {
+				Ok(resp) if resp.status < 400 => return Ok(resp),
+				Ok(resp) => (deal_error(resp, sub_url), false),
+				Err(e) => {
+					error!("<== ❌\n\tError api: {sub_url}, error: {:?}", e.message);
+					(Error::RequestError(e.message), e.retryable)
+				},
+			};
+			match self.retry_policy.retry_delay(attempt, &err, transport) {
+				Some(delay) => {
+					warn!("<== 🔁\n\tRetry api: {sub_url} in {delay:?}, attempt: {attempt}");
+					std::thread::sleep(delay);
+					attempt += 1;
+				},
+				None => return Err(err),
+			}
+		}
+	}
+}
+
+/// The settings shared by the sync and async clients to build requests.
+pub(crate) struct Client<'a> {
+	pub auth: &'a Auth,
+	pub api_url: &'a str,
+	pub azure: Option<&'a AzureConfig>,
+	/// Extra headers sent with every request.
+	pub headers: &'a [(String, String)],
+}
+
+/// Prepares a request with the authentication and extra headers, routed to Azure OpenAI
+/// if configured, `model` being the model of the request body, if any.
+pub(crate) fn new_request(
+	client: &Client,
+	method: &str,
+	sub_url: &str,
+	model: Option<&str>,
+) -> ApiResult<HttpRequest> {
+	let (url, mut headers) = match client.azure {
+		Some(azure) => (
+			azure.url(client.api_url, sub_url, model)?,
+			vec![("api-key".to_string(), client.auth.api_key.clone())],
+		),
+		None => (
+			client.api_url.to_string() + sub_url,
+			vec![
+				(
+					"OpenAI-Organization".to_string(),
+					client.auth.organization.clone().unwrap_or_default(),
+				),
+				("Authorization".to_string(), format!("Bearer {}", client.auth.api_key)),
+			],
+		),
+	};
+	headers.extend(client.headers.iter().cloned());
+	Ok(HttpRequest { method: method.to_string(), url, headers, body: None })
+}
+
+fn deal_response(response: ApiResult<HttpResponse>, sub_url: &str) -> ApiResult<Json> {
+	let body = read_body(response?)?;
+	let json = serde_json::from_str::<Json>(&body).map_err(|e| Error::decode(e, &body))?;
+	debug!("<== ✔️\n\tDone api: {sub_url}, resp: {json}");
+	Ok(json)
+}
+
+fn deal_error(response: HttpResponse, sub_url: &str) -> Error {
+	let status = response.status;
+	let headers = response.headers.clone();
+	match read_body(response) {
+		Ok(body) => api_error(status, headers, body, sub_url),
+		Err(e) => Error::IoError(e),
+	}
+}
+
+/// The error of a response with an error status.
+pub(crate) fn api_error(
+	status: u16,
+	headers: HashMap<String, String>,
+	body: String,
+	sub_url: &str,
+) -> Error {
+	// Proxies and gateways may answer with a non-JSON body, e.g. an HTML 502 page.
+	let error_msg = serde_json::from_str::<Json>(&body).unwrap_or(Json::String(body));
+	error!("<== ❌\n\tError api: {sub_url}, status: {status}, error: {error_msg}");
+	ApiError::new(Some(status), headers, error_msg).into()
+}
+
+fn read_body(mut response: HttpResponse) -> std::io::Result<String> {
+	let mut body = Vec::new();
+	response.body.read_to_end(&mut body)?;
+	Ok(String::from_utf8_lossy(&body).into_owned())
+}
+
+pub(crate) fn model(body: &Json) -> Option<&str> {
+	body.get("model")?.as_str()
+}
+
+pub(crate) fn to_bytes(body: &Json) -> ApiResult<Vec<u8>> {
+	serde_json::to_vec(body).map_err(|e| Error::RequestError(format!("Invalid body: {e}")))
+}
+
+/// Serializes a request body.
+pub(crate) fn to_json<T: Serialize>(body: &T) -> ApiResult<Json> {
+	serde_json::to_value(body).map_err(|e| Error::RequestError(format!("Invalid body: {e}")))
+}
+
+/// Deserializes a response body, keeping the body in the error if it does not match `T`.
+pub(crate) fn from_json<T: DeserializeOwned>(json: &Json) -> ApiResult<T> {
+	T::deserialize(json).map_err(|e| Error::decode(e, &json.to_string()))
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
+	use std::io::{BufRead, BufReader, Cursor, Write};
+	use std::net::TcpListener;
+	use std::thread::JoinHandle;
+
 	use super::*;
 	use crate::openai;
 	use ureq::json;
 
 	#[test]
 	fn test_post() {
-		let openai = openai::new_test_openai();
+		let (openai, transport) = openai::new_mock_openai();
+		transport.push(MockResponse::json(
+			200,
+			json!({
+				"id": "chatcmpl-123",
+				"object": "chat.completion",
+				"created": 1677652288,
+				"model": "gpt-3.5-turbo-0125",
+				"choices": [{
+					"index": 0,
+					"message": { "role": "assistant", "content": "This is a test!" },
+					"finish_reason": "stop"
+				}]
+			}),
+		));
 		let body = json!({
 			"model": "gpt-3.5-turbo",
 			"messages": [{"role": "user", "content": "Say this is a test!"}],
 			"temperature": 0.7
 		});
 		let sub_url = "chat/completions";
-		let result = openai.post(sub_url, body).unwrap();
+		let result = openai.post(sub_url, body.clone()).unwrap();
 		assert!(result.to_string().contains("This is a test"));
+
+		let request = &transport.requests()[0];
+		assert_eq!(request.method, "POST");
+		assert_eq!(request.url, "https://api.openai.com/v1/chat/completions");
+		assert_eq!(request.header("authorization"), Some("Bearer sk-test"));
+		assert_eq!(request.header("content-type"), Some("application/json"));
+		assert_eq!(request.json(), Some(body));
 	}
 
 	#[test]
 	fn test_get() {
-		let openai = openai::new_test_openai();
+		let (openai, transport) = openai::new_mock_openai();
+		transport.push(MockResponse::json(
+			200,
+			json!({
+				"object": "list",
+				"data": [{ "id": "babbage-002", "object": "model", "created": 1692634615, "owned_by": "system" }]
+			}),
+		));
 		let resp = openai.get("models").unwrap();
 		assert!(resp.to_string().contains("babbage-002"));
+
+		let request = &transport.requests()[0];
+		assert_eq!(request.method, "GET");
+		assert_eq!(request.url, "https://api.openai.com/v1/models");
+		assert_eq!(request.body, None);
+	}
+
+	/// Answers each request with the next raw HTTP response, returns the API url
+	/// and a handle giving the received requests.
+	pub(crate) fn serve(responses: &'static [&'static str]) -> (String, JoinHandle<Vec<String>>) {
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let url = format!("http://{}/v1/", listener.local_addr().unwrap());
+		let handle = std::thread::spawn(move || {
+			let mut requests = Vec::new();
+			for response in responses {
+				let (stream, _) = listener.accept().unwrap();
+				let mut reader = BufReader::new(stream);
+				let mut request = String::new();
+				let mut content_length = 0;
+				let mut chunked = false;
+				loop {
+					let mut line = String::new();
+					reader.read_line(&mut line).unwrap();
+					if let Some((name, value)) = line.split_once(':') {
+						if name.eq_ignore_ascii_case("content-length") {
+							content_length = value.trim().parse().unwrap();
+						}
+						chunked |= name.eq_ignore_ascii_case("transfer-encoding");
+					}
+					request.push_str(&line);
+					if line == "\r\n" {
+						break;
+					}
+				}
+				let mut body = vec![0; content_length];
+				reader.read_exact(&mut body).unwrap();
+				// Chunks are `<size in hex>\r\n<data>\r\n`, ending with an empty chunk.
+				while chunked {
+					let mut size = String::new();
+					reader.read_line(&mut size).unwrap();
+					let size = usize::from_str_radix(size.trim(), 16).unwrap();
+					let mut chunk = vec![0; size + 2];
+					reader.read_exact(&mut chunk).unwrap();
+					body.extend_from_slice(&chunk[..size]);
+					chunked = size > 0;
+				}
+				request.push_str(&String::from_utf8_lossy(&body));
+				requests.push(request);
+				reader.get_mut().write_all(response.as_bytes()).unwrap();
+			}
+			requests
+		});
+		(url, handle)
+	}
+
+	#[test]
+	fn test_non_json_error() {
+		let (url, _) = serve(&[
+			"HTTP/1.1 502 Bad Gateway\r\nContent-Type: text/html\r\nContent-Length: 21\r\n\
+			 Connection: close\r\n\r\n<html>Gateway</html>\n",
+		]);
+		let openai =
+			OpenAI::new(openai::Auth::new("key"), &url).set_retry_policy(RetryPolicy::none());
+		match openai.get("models") {
+			Err(Error::ApiError(err)) => {
+				assert_eq!(err.status, Some(502));
+				assert_eq!(err.message, "<html>Gateway</html>\n");
+			},
+			other => panic!("unexpected result: {other:?}"),
+		}
+	}
+
+	#[test]
+	fn test_non_json_success() {
+		let (url, _) =
+			serve(&["HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: 14\r\n\
+			 Connection: close\r\n\r\n<html>OK</html"]);
+		let openai = OpenAI::new(openai::Auth::new("key"), &url);
+		match openai.get("models") {
+			Err(Error::DecodeError { body, .. }) => assert_eq!(body, "<html>OK</html"),
+			other => panic!("unexpected result: {other:?}"),
+		}
+	}
+
+	const RATE_LIMITED: &str =
+		"HTTP/1.1 429 Too Many Requests\r\nContent-Type: application/json\r\n\
+		retry-after-ms: 10\r\nContent-Length: 44\r\nConnection: close\r\n\r\n\
+		{\"error\": {\"message\": \"Rate limit reached\"}}";
+	pub(crate) const OK: &str =
+		"HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 12\r\n\
+		Connection: close\r\n\r\n{\"ok\": true}";
+
+	#[test]
+	fn test_retry() {
+		let (url, handle) = serve(&[RATE_LIMITED, RATE_LIMITED, OK]);
+		let openai = OpenAI::new(openai::Auth::new("key"), &url);
+		let resp = openai.post("chat/completions", json!({ "model": "gpt-4o" })).unwrap();
+		assert_eq!(resp, json!({ "ok": true }));
+		let requests = handle.join().unwrap();
+		assert!(requests.iter().all(|r| r.ends_with("{\"model\":\"gpt-4o\"}")));
+	}
+
+	#[test]
+	fn test_retry_gives_up() {
+		let (url, _) = serve(&[RATE_LIMITED, RATE_LIMITED]);
+		let policy = RetryPolicy { max_attempts: 2, ..Default::default() };
+		let openai = OpenAI::new(openai::Auth::new("key"), &url).set_retry_policy(policy);
+		match openai.get("models") {
+			Err(Error::ApiError(err)) => assert!(err.is_rate_limited()),
+			other => panic!("unexpected result: {other:?}"),
+		}
+	}
+
+	#[test]
+	fn test_retry_multipart() {
+		let (url, handle) = serve(&[RATE_LIMITED, OK]);
+		let openai = OpenAI::new(openai::Auth::new("key"), &url);
+		let mut multipart = Multipart::new();
+		multipart.add_stream("file", Cursor::new(b"file content"), Some("a.txt"), None);
+		openai.post_multipart("files", multipart).unwrap();
+		let requests = handle.join().unwrap();
+		assert_eq!(requests.len(), 2);
+		assert!(requests.iter().all(|r| r.contains("file content")));
+	}
+
+	#[test]
+	fn test_multipart_streamed_without_retries() {
+		let (url, handle) = serve(&[OK]);
+		let openai =
+			OpenAI::new(openai::Auth::new("key"), &url).set_retry_policy(RetryPolicy::none());
+		let mut multipart = Multipart::new();
+		multipart.add_stream("file", Cursor::new(b"file content"), Some("a.txt"), None);
+		openai.post_multipart("files", multipart).unwrap();
+		let request = handle.join().unwrap().remove(0);
+		assert!(request.to_lowercase().contains("transfer-encoding: chunked\r\n"));
+		assert!(request.contains("file content"));
+	}
+
+	#[test]
+	fn test_retry_transport_error() {
+		let (openai, transport) = openai::new_mock_openai();
+		let openai = openai.set_retry_policy(RetryPolicy {
+			base_delay: std::time::Duration::from_millis(1),
+			..Default::default()
+		});
+		let reset = TransportError { message: "Connection reset".to_string(), retryable: true };
+		transport.push_error(reset).push(MockResponse::json(200, json!({ "ok": true })));
+		assert_eq!(openai.get("models").unwrap(), json!({ "ok": true }));
+		assert_eq!(transport.requests().len(), 2);
+
+		let refused = TransportError { message: "Invalid URL".to_string(), retryable: false };
+		transport.push_error(refused).push(MockResponse::json(200, json!({ "ok": true })));
+		assert!(matches!(openai.get("models"), Err(Error::RequestError(_))));
+		assert_eq!(transport.requests().len(), 3);
+	}
+
+	#[test]
+	fn test_invalid_proxy() {
+		let openai = OpenAI::new(openai::Auth::new("key"), "https://api.openai.com/v1/");
+		assert!(matches!(openai.set_proxy("ftp://127.0.0.1:1080"), Err(Error::InvalidProxy(_))));
 	}
 }
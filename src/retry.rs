@@ -0,0 +1,193 @@
+//! Retrying failed requests
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::Error;
+
+/// When and how long to wait before retrying a failed request.
+///
+/// Rate limited (`429`) and server errors are retried with an exponential backoff,
+/// unless the response tells how long to wait with the `retry-after-ms`, `retry-after`
+/// or `x-ratelimit-reset-*` headers. Exhausted quotas are never retried.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+	/// The maximum number of attempts, including the first one.
+	///
+	/// Uploads, e.g. of files or audio, are buffered in memory to be sent again when
+	/// retries are allowed, and streamed from their reader when this is 1. The async
+	/// client always buffers them.
+	/// Defaults to 3
+	pub max_attempts: u32,
+	/// The delay before the first retry, doubled for each following retry.
+	/// Defaults to 500ms
+	pub base_delay: Duration,
+	/// The maximum delay between two attempts, including delays asked by the server.
+	/// Defaults to 30s
+	pub max_delay: Duration,
+	/// The fraction of the backoff delay which is randomly removed, between 0 and 1,
+	/// so that concurrent clients do not retry all at once.
+	/// Defaults to 0.25
+	pub jitter: f64,
+	/// The HTTP statuses which are retried.
+	/// Defaults to 408, 409, 429, 500, 502, 503 and 504
+	pub retry_statuses: Vec<u16>,
+	/// Whether to retry when the connection fails or is reset.
+	/// Defaults to true
+	pub retry_transport_errors: bool,
+}
+
+impl Default for RetryPolicy {
+	fn default() -> Self {
+		Self {
+			max_attempts: 3,
+			base_delay: Duration::from_millis(500),
+			max_delay: Duration::from_secs(30),
+			jitter: 0.25,
+			retry_statuses: vec![408, 409, 429, 500, 502, 503, 504],
+			retry_transport_errors: true,
+		}
+	}
+}
+
+impl RetryPolicy {
+	/// Never retries.
+	pub fn none() -> Self {
+		Self { max_attempts: 1, ..Default::default() }
+	}
+
+	/// Returns how long to wait before the next attempt, or `None` if `err` must be returned.
+	///
+	/// `attempt` starts at 1, `transport` tells whether `err` is a connection failure.
+	pub(crate) fn retry_delay(
+		&self,
+		attempt: u32,
+		err: &Error,
+		transport: bool,
+	) -> Option<Duration> {
+		if attempt >= self.max_attempts {
+			return None;
+		}
+		match err {
+			Error::ApiError(err) => {
+				let status = err.status?;
+				if !self.retry_statuses.contains(&status) || err.is_quota_exceeded() {
+					return None;
+				}
+				let delay = server_delay(&err.headers).unwrap_or_else(|| self.backoff(attempt));
+				Some(delay.min(self.max_delay))
+			},
+			Error::RequestError(_) if transport && self.retry_transport_errors => {
+				Some(self.backoff(attempt))
+			},
+			_ => None,
+		}
+	}
+
+	fn backoff(&self, attempt: u32) -> Duration {
+		let exp = self.base_delay.saturating_mul(1 << (attempt - 1).min(16)).min(self.max_delay);
+		exp.mul_f64(1.0 - self.jitter.clamp(0.0, 1.0) * rand::random::<f64>())
+	}
+}
+
+/// The delay asked by the server, if any.
+fn server_delay(headers: &HashMap<String, String>) -> Option<Duration> {
+	if let Some(ms) = headers.get("retry-after-ms").and_then(|v| v.trim().parse::<f64>().ok()) {
+		return Duration::try_from_secs_f64(ms / 1000.0).ok();
+	}
+	// `retry-after` may also be an HTTP date, which is not supported.
+	if let Some(secs) = headers.get("retry-after").and_then(|v| v.trim().parse::<f64>().ok()) {
+		return Duration::try_from_secs_f64(secs).ok();
+	}
+	["x-ratelimit-reset-requests", "x-ratelimit-reset-tokens"]
+		.iter()
+		.filter_map(|name| parse_reset(headers.get(*name)?))
+		.max()
+}
+
+/// Parses a duration such as `20ms`, `1s` or `6m0.5s`.
+fn parse_reset(value: &str) -> Option<Duration> {
+	let mut rest = value.trim();
+	if rest.is_empty() {
+		return None;
+	}
+	let mut total = 0.0;
+	while !rest.is_empty() {
+		let end = rest.find(|c: char| !c.is_ascii_digit() && c != '.')?;
+		let number: f64 = rest[..end].parse().ok()?;
+		rest = &rest[end..];
+		let (unit, secs) = ["ms", "h", "m", "s"]
+			.iter()
+			.zip([0.001, 3600.0, 60.0, 1.0])
+			.find(|(unit, _)| rest.starts_with(**unit))?;
+		total += number * secs;
+		rest = &rest[unit.len()..];
+	}
+	Duration::try_from_secs_f64(total).ok()
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+
+	use serde_json::json;
+
+	use super::{parse_reset, RetryPolicy};
+	use crate::{ApiError, Error};
+
+	fn api_error(status: u16, headers: &[(&str, &str)], code: &str) -> Error {
+		let headers = headers.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+		ApiError::new(Some(status), headers, json!({ "error": { "message": "", "code": code } }))
+			.into()
+	}
+
+	#[test]
+	fn test_parse_reset() {
+		assert_eq!(parse_reset("20ms"), Some(Duration::from_millis(20)));
+		assert_eq!(parse_reset("1s"), Some(Duration::from_secs(1)));
+		assert_eq!(parse_reset("6m0.5s"), Some(Duration::from_millis(360_500)));
+		assert_eq!(parse_reset("1h2m"), Some(Duration::from_secs(3720)));
+		assert_eq!(parse_reset(""), None);
+		assert_eq!(parse_reset("soon"), None);
+	}
+
+	#[test]
+	fn test_retry_delay() {
+		let policy = RetryPolicy { jitter: 0.0, ..Default::default() };
+		let rate_limited = api_error(429, &[], "rate_limit_exceeded");
+		assert_eq!(policy.retry_delay(1, &rate_limited, false), Some(Duration::from_millis(500)));
+		assert_eq!(policy.retry_delay(2, &rate_limited, false), Some(Duration::from_secs(1)));
+		assert_eq!(policy.retry_delay(3, &rate_limited, false), None);
+
+		let quota = api_error(429, &[], "insufficient_quota");
+		assert_eq!(policy.retry_delay(1, &quota, false), None);
+		let bad_request = api_error(400, &[], "invalid_request_error");
+		assert_eq!(policy.retry_delay(1, &bad_request, false), None);
+
+		let transport = Error::RequestError("Connection reset".to_string());
+		assert_eq!(policy.retry_delay(1, &transport, true), Some(Duration::from_millis(500)));
+		assert_eq!(policy.retry_delay(1, &transport, false), None);
+		assert_eq!(RetryPolicy::none().retry_delay(1, &transport, true), None);
+	}
+
+	#[test]
+	fn test_retry_delay_from_headers() {
+		let policy = RetryPolicy::default();
+		let delay = |headers: &[(&str, &str)]| {
+			policy.retry_delay(1, &api_error(503, headers, "server_error"), false)
+		};
+		assert_eq!(delay(&[("retry-after-ms", "150")]), Some(Duration::from_millis(150)));
+		assert_eq!(delay(&[("retry-after", "2")]), Some(Duration::from_secs(2)));
+		assert_eq!(
+			delay(&[("x-ratelimit-reset-requests", "1s"), ("x-ratelimit-reset-tokens", "6m0s")]),
+			Some(Duration::from_secs(30))
+		);
+		assert_eq!(
+			delay(&[("x-ratelimit-reset-requests", "120ms"), ("x-ratelimit-reset-tokens", "2s")]),
+			Some(Duration::from_secs(2))
+		);
+
+		let jittered = delay(&[]).unwrap();
+		assert!(jittered <= Duration::from_millis(500) && jittered >= Duration::from_millis(375));
+	}
+}
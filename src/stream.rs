@@ -0,0 +1,223 @@
+//! Server-sent events
+
+use std::collections::{HashMap, VecDeque};
+use std::io::{ErrorKind, Read};
+use std::marker::PhantomData;
+
+use serde::de::DeserializeOwned;
+
+use crate::*;
+
+/// A blocking iterator over the `data:` events of a server-sent events response.
+///
+/// Each event is deserialized into `T`. The iterator ends when the server sends
+/// `data: [DONE]` or closes the connection, and yields an `Err` (then ends) if
+/// the server reports an error in the middle of the stream.
+pub struct EventStream<T> {
+	reader: Box<dyn Read + Send>,
+	decoder: EventDecoder,
+	_marker: PhantomData<T>,
+}
+
+impl<T> EventStream<T> {
+	pub(crate) fn new(reader: Box<dyn Read + Send>) -> Self {
+		Self { reader, decoder: EventDecoder::default(), _marker: PhantomData }
+	}
+}
+
+impl<T: DeserializeOwned> Iterator for EventStream<T> {
+	type Item = ApiResult<T>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		let mut buf = [0; 4096];
+		loop {
+			if self.decoder.done {
+				return None;
+			}
+			if let Some(event) = self.decoder.next_event() {
+				return Some(event);
+			}
+			match self.reader.read(&mut buf) {
+				Ok(0) => self.decoder.finish(),
+				Ok(n) => self.decoder.feed(&buf[..n]),
+				Err(e) if e.kind() == ErrorKind::Interrupted => {},
+				Err(e) => {
+					self.decoder.done = true;
+					return Some(Err(Error::IoError(e)));
+				},
+			}
+		}
+	}
+}
+
+/// The async counterpart of [`EventStream`].
+#[cfg(feature = "async")]
+pub struct AsyncEventStream<T> {
+	body: Box<dyn AsyncBody>,
+	decoder: EventDecoder,
+	_marker: PhantomData<T>,
+}
+
+#[cfg(feature = "async")]
+impl<T: DeserializeOwned> AsyncEventStream<T> {
+	pub(crate) fn new(body: Box<dyn AsyncBody>) -> Self {
+		Self { body, decoder: EventDecoder::default(), _marker: PhantomData }
+	}
+
+	/// Waits for the next event, or returns `None` once the stream is exhausted.
+	pub async fn next(&mut self) -> Option<ApiResult<T>> {
+		loop {
+			if self.decoder.done {
+				return None;
+			}
+			if let Some(event) = self.decoder.next_event() {
+				return Some(event);
+			}
+			match self.body.chunk().await {
+				Ok(Some(bytes)) => self.decoder.feed(&bytes),
+				Ok(None) => self.decoder.finish(),
+				Err(e) => {
+					self.decoder.done = true;
+					return Some(Err(Error::IoError(e)));
+				},
+			}
+		}
+	}
+}
+
+/// Splits the bytes of a server-sent events response into the `data` of each event.
+#[derive(Default)]
+struct EventDecoder {
+	/// Bytes of the current, incomplete line.
+	line: Vec<u8>,
+	/// `data` lines of the current, incomplete event.
+	data: String,
+	events: VecDeque<String>,
+	eof: bool,
+	done: bool,
+}
+
+impl EventDecoder {
+	fn feed(&mut self, bytes: &[u8]) {
+		for &byte in bytes {
+			if byte == b'\n' {
+				let line = std::mem::take(&mut self.line);
+				self.feed_line(&String::from_utf8_lossy(&line));
+			} else {
+				self.line.push(byte);
+			}
+		}
+	}
+
+	/// Flushes the last event once the response is exhausted.
+	fn finish(&mut self) {
+		let line = std::mem::take(&mut self.line);
+		self.feed_line(&String::from_utf8_lossy(&line));
+		self.feed_line("");
+		self.eof = true;
+	}
+
+	fn feed_line(&mut self, line: &str) {
+		let line = line.strip_suffix('\r').unwrap_or(line);
+		if line.is_empty() {
+			if !self.data.is_empty() {
+				self.events.push_back(std::mem::take(&mut self.data));
+			}
+			return;
+		}
+		// Comments, `event:`, `id:` and `retry:` fields are not used by the API.
+		if let Some(value) = line.strip_prefix("data:") {
+			if !self.data.is_empty() {
+				self.data.push('\n');
+			}
+			self.data.push_str(value.strip_prefix(' ').unwrap_or(value));
+		}
+	}
+
+	/// Decodes the next complete event, if any.
+	fn next_event<T: DeserializeOwned>(&mut self) -> Option<ApiResult<T>> {
+		let data = match self.events.pop_front() {
+			Some(data) => data,
+			None => {
+				self.done = self.eof;
+				return None;
+			},
+		};
+		if data == "[DONE]" {
+			self.done = true;
+			return None;
+		}
+		let rs = parse_event(&data);
+		if rs.is_err() {
+			self.done = true;
+		}
+		Some(rs)
+	}
+}
+
+fn parse_event<T: DeserializeOwned>(data: &str) -> ApiResult<T> {
+	let json: Json = serde_json::from_str(data).map_err(|e| Error::decode(e, data))?;
+	if json.get("error").is_some() {
+		return Err(ApiError::new(None, HashMap::new(), json).into());
+	}
+	T::deserialize(&json).map_err(|e| Error::decode(e, data))
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::{Cursor, Read};
+
+	use super::EventStream;
+	use crate::{Error, Json};
+
+	fn new_stream(data: &str) -> EventStream<Json> {
+		EventStream::new(Box::new(Cursor::new(data.to_string().into_bytes())))
+	}
+
+	#[test]
+	fn test_stream_until_done() {
+		let stream = new_stream(
+			": keep-alive\n\ndata: {\"n\": 1}\n\ndata: {\"n\": 2}\r\n\r\ndata: [DONE]\n\ndata: {\"n\": 3}\n\n",
+		);
+		let events: Vec<Json> = stream.map(|e| e.unwrap()).collect();
+		assert_eq!(events.len(), 2);
+		assert_eq!(events[1]["n"], 2);
+	}
+
+	#[test]
+	fn test_stream_without_done() {
+		let stream = new_stream("data: {\"n\": 1}\n\ndata: {\"n\": 2}");
+		assert_eq!(stream.count(), 2);
+	}
+
+	#[test]
+	fn test_stream_split_reads() {
+		/// Returns one byte per read, splitting lines and multi-byte characters.
+		struct OneByte(Cursor<Vec<u8>>);
+
+		impl Read for OneByte {
+			fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+				self.0.read(&mut buf[..1])
+			}
+		}
+
+		let data = "data: {\"text\": \"千里\"}\n\ndata: [DONE]\n\n";
+		let stream: EventStream<Json> =
+			EventStream::new(Box::new(OneByte(Cursor::new(data.as_bytes().to_vec()))));
+		let events: Vec<Json> = stream.map(|e| e.unwrap()).collect();
+		assert_eq!(events, vec![serde_json::json!({"text": "千里"})]);
+	}
+
+	#[test]
+	fn test_stream_error() {
+		let mut stream = new_stream(
+			"data: {\"n\": 1}\n\ndata: {\"error\": {\"message\": \"overloaded\"}}\n\ndata: {\"n\": 2}\n\n",
+		);
+		assert!(stream.next().unwrap().is_ok());
+		match stream.next() {
+			Some(Err(Error::ApiError(err))) => assert_eq!(err.message, "overloaded"),
+			other => panic!("unexpected event: {other:?}"),
+		}
+		assert!(stream.next().is_none());
+	}
+}
@@ -0,0 +1,450 @@
+//! Sending HTTP requests
+//!
+//! [`OpenAI`](crate::OpenAI) sends every request through a [`Transport`],
+//! [`UreqTransport`] by default, and `AsyncOpenAI` through an `AsyncTransport`,
+//! `ReqwestTransport` by default. [`MockTransport`] implements both and replays
+//! canned responses to test code calling the API without a network.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{self, Debug, Display, Formatter};
+use std::io::{Cursor, Read};
+use std::sync::{Arc, Mutex};
+
+#[cfg(feature = "async")]
+use std::{future::Future, io, pin::Pin};
+
+use crate::Json;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpRequest {
+	pub method: String,
+	pub url: String,
+	pub headers: Vec<(String, String)>,
+	pub body: Option<Vec<u8>>,
+}
+
+impl HttpRequest {
+	/// Returns the value of a header, ignoring the case of its name.
+	pub fn header(&self, name: &str) -> Option<&str> {
+		self.headers.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, v)| v.as_str())
+	}
+
+	/// Parses the body as JSON.
+	pub fn json(&self) -> Option<Json> {
+		serde_json::from_slice(self.body.as_deref()?).ok()
+	}
+}
+
+pub struct HttpResponse {
+	pub status: u16,
+	/// The response headers, with lowercase names.
+	pub headers: HashMap<String, String>,
+	pub body: Box<dyn Read + Send>,
+}
+
+impl Debug for HttpResponse {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		f.debug_struct("HttpResponse")
+			.field("status", &self.status)
+			.field("headers", &self.headers)
+			.finish_non_exhaustive()
+	}
+}
+
+/// The request could not be sent, or the response could not be received.
+#[derive(Debug, Clone)]
+pub struct TransportError {
+	pub message: String,
+	/// Whether sending the request again may succeed, e.g. when the connection was reset.
+	pub retryable: bool,
+}
+
+impl Display for TransportError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(f, "{}", self.message)
+	}
+}
+
+/// Sends HTTP requests for [`OpenAI`](crate::OpenAI).
+///
+/// Responses with an error status are returned as `Ok`, [`TransportError`] is
+/// for requests which got no response at all.
+pub trait Transport: Debug + Send + Sync {
+	fn send(&self, request: HttpRequest) -> Result<HttpResponse, TransportError>;
+
+	/// Sends a request whose body is streamed from `body`, e.g. a file upload,
+	/// ignoring `request.body`.
+	///
+	/// Defaults to reading the whole body in memory and calling [`send`](Transport::send).
+	fn send_reader(
+		&self,
+		request: HttpRequest,
+		body: &mut dyn Read,
+	) -> Result<HttpResponse, TransportError> {
+		let mut bytes = Vec::new();
+		body.read_to_end(&mut bytes)
+			.map_err(|e| TransportError { message: e.to_string(), retryable: false })?;
+		self.send(HttpRequest { body: Some(bytes), ..request })
+	}
+}
+
+impl<T: Transport + ?Sized> Transport for Arc<T> {
+	fn send(&self, request: HttpRequest) -> Result<HttpResponse, TransportError> {
+		(**self).send(request)
+	}
+
+	fn send_reader(
+		&self,
+		request: HttpRequest,
+		body: &mut dyn Read,
+	) -> Result<HttpResponse, TransportError> {
+		(**self).send_reader(request, body)
+	}
+}
+
+/// The default transport, built on a `ureq::Agent`.
+#[derive(Debug, Clone)]
+pub struct UreqTransport {
+	agent: ureq::Agent,
+}
+
+impl UreqTransport {
+	pub fn new(agent: ureq::Agent) -> UreqTransport {
+		UreqTransport { agent }
+	}
+
+	fn request(&self, request: &HttpRequest) -> ureq::Request {
+		request
+			.headers
+			.iter()
+			.fold(self.agent.request(&request.method, &request.url), |req, (name, value)| {
+				req.set(name, value)
+			})
+	}
+}
+
+impl Default for UreqTransport {
+	fn default() -> Self {
+		UreqTransport::new(ureq::AgentBuilder::new().build())
+	}
+}
+
+impl Transport for UreqTransport {
+	fn send(&self, request: HttpRequest) -> Result<HttpResponse, TransportError> {
+		let req = self.request(&request);
+		into_response(match &request.body {
+			Some(body) => req.send_bytes(body),
+			None => req.call(),
+		})
+	}
+
+	/// Streams the body with a chunked transfer encoding.
+	fn send_reader(
+		&self,
+		request: HttpRequest,
+		body: &mut dyn Read,
+	) -> Result<HttpResponse, TransportError> {
+		into_response(self.request(&request).send(body))
+	}
+}
+
+fn into_response(
+	response: Result<ureq::Response, ureq::Error>,
+) -> Result<HttpResponse, TransportError> {
+	match response {
+		Ok(response) | Err(ureq::Error::Status(_, response)) => {
+			let headers = response
+				.headers_names()
+				.into_iter()
+				.filter_map(|name| {
+					let value = response.header(&name)?.to_string();
+					Some((name.to_lowercase(), value))
+				})
+				.collect();
+			Ok(HttpResponse { status: response.status(), headers, body: response.into_reader() })
+		},
+		Err(ureq::Error::Transport(e)) => Err(TransportError {
+			retryable: matches!(e.kind(), ureq::ErrorKind::Io | ureq::ErrorKind::ConnectionFailed),
+			message: e.to_string(),
+		}),
+	}
+}
+
+/// A boxed future, keeping [`AsyncTransport`] usable as a trait object.
+#[cfg(feature = "async")]
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// The body of an [`AsyncHttpResponse`], read chunk by chunk.
+#[cfg(feature = "async")]
+pub trait AsyncBody: Send {
+	/// Returns the next chunk of the body, or `None` once it is exhausted.
+	fn chunk(&mut self) -> BoxFuture<'_, io::Result<Option<Vec<u8>>>>;
+}
+
+/// A body already in memory, returned as a single chunk.
+#[cfg(feature = "async")]
+impl AsyncBody for Vec<u8> {
+	fn chunk(&mut self) -> BoxFuture<'_, io::Result<Option<Vec<u8>>>> {
+		let chunk = std::mem::take(self);
+		Box::pin(async move { Ok(Some(chunk).filter(|chunk| !chunk.is_empty())) })
+	}
+}
+
+#[cfg(feature = "async")]
+impl AsyncBody for reqwest::Response {
+	fn chunk(&mut self) -> BoxFuture<'_, io::Result<Option<Vec<u8>>>> {
+		Box::pin(async move {
+			let chunk = reqwest::Response::chunk(self).await.map_err(io::Error::other)?;
+			Ok(chunk.map(|bytes| bytes.to_vec()))
+		})
+	}
+}
+
+/// The async counterpart of [`HttpResponse`].
+#[cfg(feature = "async")]
+pub struct AsyncHttpResponse {
+	pub status: u16,
+	/// The response headers, with lowercase names.
+	pub headers: HashMap<String, String>,
+	pub body: Box<dyn AsyncBody>,
+}
+
+#[cfg(feature = "async")]
+impl Debug for AsyncHttpResponse {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		f.debug_struct("AsyncHttpResponse")
+			.field("status", &self.status)
+			.field("headers", &self.headers)
+			.finish_non_exhaustive()
+	}
+}
+
+/// The async counterpart of [`Transport`], sending HTTP requests for `AsyncOpenAI`.
+#[cfg(feature = "async")]
+pub trait AsyncTransport: Debug + Send + Sync {
+	fn send(
+		&self,
+		request: HttpRequest,
+	) -> BoxFuture<'_, Result<AsyncHttpResponse, TransportError>>;
+}
+
+#[cfg(feature = "async")]
+impl<T: AsyncTransport + ?Sized> AsyncTransport for Arc<T> {
+	fn send(
+		&self,
+		request: HttpRequest,
+	) -> BoxFuture<'_, Result<AsyncHttpResponse, TransportError>> {
+		(**self).send(request)
+	}
+}
+
+/// The default async transport, built on a `reqwest::Client`.
+#[cfg(feature = "async")]
+#[derive(Debug, Clone)]
+pub struct ReqwestTransport {
+	client: reqwest::Client,
+}
+
+#[cfg(feature = "async")]
+impl ReqwestTransport {
+	pub fn new(client: reqwest::Client) -> ReqwestTransport {
+		ReqwestTransport { client }
+	}
+}
+
+#[cfg(feature = "async")]
+impl Default for ReqwestTransport {
+	/// # Panics
+	///
+	/// Like `reqwest::Client::new`, panics if the TLS backend cannot be initialized.
+	fn default() -> Self {
+		ReqwestTransport::new(reqwest::Client::builder().no_proxy().build().expect("TLS backend"))
+	}
+}
+
+#[cfg(feature = "async")]
+impl AsyncTransport for ReqwestTransport {
+	fn send(
+		&self,
+		request: HttpRequest,
+	) -> BoxFuture<'_, Result<AsyncHttpResponse, TransportError>> {
+		Box::pin(async move {
+			let method = reqwest::Method::from_bytes(request.method.as_bytes())
+				.map_err(|e| TransportError { message: e.to_string(), retryable: false })?;
+			let mut req = self.client.request(method, &request.url);
+			for (name, value) in &request.headers {
+				req = req.header(name, value);
+			}
+			if let Some(body) = request.body {
+				req = req.body(body);
+			}
+			let response = req.send().await.map_err(|e| TransportError {
+				retryable: e.is_connect() || e.is_timeout(),
+				message: e.to_string(),
+			})?;
+			let headers = response
+				.headers()
+				.iter()
+				.filter_map(|(name, value)| {
+					Some((name.to_string(), value.to_str().ok()?.to_string()))
+				})
+				.collect();
+			Ok(AsyncHttpResponse {
+				status: response.status().as_u16(),
+				headers,
+				body: Box::new(response),
+			})
+		})
+	}
+}
+
+/// A canned response of [`MockTransport`].
+#[derive(Debug, Clone)]
+pub struct MockResponse {
+	pub status: u16,
+	pub headers: HashMap<String, String>,
+	pub body: Vec<u8>,
+}
+
+impl MockResponse {
+	pub fn new(status: u16, body: impl Into<Vec<u8>>) -> MockResponse {
+		MockResponse { status, headers: HashMap::new(), body: body.into() }
+	}
+
+	pub fn json(status: u16, body: Json) -> MockResponse {
+		MockResponse::new(status, body.to_string()).header("content-type", "application/json")
+	}
+
+	/// A server-sent events response, sending each event as a `data:` line then `[DONE]`.
+	pub fn events(events: &[Json]) -> MockResponse {
+		let mut body = String::new();
+		for event in events {
+			body += &format!("data: {event}\n\n");
+		}
+		body += "data: [DONE]\n\n";
+		MockResponse::new(200, body).header("content-type", "text/event-stream")
+	}
+
+	pub fn header(mut self, name: &str, value: &str) -> MockResponse {
+		self.headers.insert(name.to_lowercase(), value.to_string());
+		self
+	}
+}
+
+/// An in-memory transport which records the requests and answers them with canned
+/// responses, in order.
+///
+/// Clones share their state, so a clone can be kept to inspect the requests.
+/// Once the responses are exhausted, requests fail with a [`TransportError`].
+#[derive(Debug, Clone, Default)]
+pub struct MockTransport {
+	state: Arc<Mutex<MockState>>,
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+	responses: VecDeque<Result<MockResponse, TransportError>>,
+	requests: Vec<HttpRequest>,
+}
+
+impl MockTransport {
+	pub fn new() -> MockTransport {
+		Default::default()
+	}
+
+	/// Queues the response to the next request.
+	pub fn push(&self, response: MockResponse) -> &MockTransport {
+		self.lock().responses.push_back(Ok(response));
+		self
+	}
+
+	/// Queues a failure, e.g. a connection reset, for the next request.
+	pub fn push_error(&self, error: TransportError) -> &MockTransport {
+		self.lock().responses.push_back(Err(error));
+		self
+	}
+
+	/// The requests received so far.
+	pub fn requests(&self) -> Vec<HttpRequest> {
+		self.lock().requests.clone()
+	}
+
+	fn lock(&self) -> std::sync::MutexGuard<'_, MockState> {
+		self.state.lock().unwrap_or_else(|e| e.into_inner())
+	}
+
+	/// Records the request and takes the next response.
+	fn next_response(&self, request: HttpRequest) -> Result<MockResponse, TransportError> {
+		let mut state = self.lock();
+		state.requests.push(request);
+		state.responses.pop_front().unwrap_or_else(|| {
+			Err(TransportError { message: "No mock response left".to_string(), retryable: false })
+		})
+	}
+}
+
+impl Transport for MockTransport {
+	fn send(&self, request: HttpRequest) -> Result<HttpResponse, TransportError> {
+		let response = self.next_response(request)?;
+		Ok(HttpResponse {
+			status: response.status,
+			headers: response.headers,
+			body: Box::new(Cursor::new(response.body)),
+		})
+	}
+}
+
+#[cfg(feature = "async")]
+impl AsyncTransport for MockTransport {
+	fn send(
+		&self,
+		request: HttpRequest,
+	) -> BoxFuture<'_, Result<AsyncHttpResponse, TransportError>> {
+		let response = self.next_response(request);
+		Box::pin(async move {
+			let response = response?;
+			Ok(AsyncHttpResponse {
+				status: response.status,
+				headers: response.headers,
+				body: Box::new(response.body),
+			})
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use serde_json::json;
+
+	use super::{HttpRequest, MockResponse, MockTransport, Transport};
+
+	fn request(url: &str) -> HttpRequest {
+		HttpRequest {
+			method: "POST".to_string(),
+			url: url.to_string(),
+			headers: vec![("Content-Type".to_string(), "application/json".to_string())],
+			body: Some(b"{\"n\": 1}".to_vec()),
+		}
+	}
+
+	#[test]
+	fn test_mock_transport() {
+		let transport = MockTransport::new();
+		transport.push(MockResponse::json(201, json!({ "id": 1 })).header("X-Request-Id", "req_1"));
+
+		let mut response = transport.clone().send(request("https://a/1")).unwrap();
+		assert_eq!(response.status, 201);
+		assert_eq!(response.headers["x-request-id"], "req_1");
+		let mut body = String::new();
+		response.body.read_to_string(&mut body).unwrap();
+		assert_eq!(body, r#"{"id":1}"#);
+
+		let err = transport.send(request("https://a/2")).unwrap_err();
+		assert!(!err.retryable);
+
+		let requests = transport.requests();
+		assert_eq!(requests.len(), 2);
+		assert_eq!(requests[0].header("content-type"), Some("application/json"));
+		assert_eq!(requests[1].json(), Some(json!({ "n": 1 })));
+	}
+}
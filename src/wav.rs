@@ -0,0 +1,102 @@
+//! Reading and writing integer PCM WAV files, to split long recordings into chunks.
+
+use crate::apis::audio::PcmFormat;
+
+/// The size of the header written by [`write`].
+pub const HEADER_LEN: usize = 44;
+
+const PCM: u16 = 1;
+const EXTENSIBLE: u16 = 0xfffe;
+
+/// Parses a WAV file into its format and samples.
+pub fn parse(bytes: &[u8]) -> Result<(PcmFormat, &[u8]), String> {
+	if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+		return Err("not a RIFF WAVE file".to_string());
+	}
+	let mut format = None;
+	let mut rest = &bytes[12..];
+	while rest.len() >= 8 {
+		let id = &rest[0..4];
+		let size = u32::from_le_bytes([rest[4], rest[5], rest[6], rest[7]]) as usize;
+		// Recorders streaming to disk may leave the size of the last chunk unset.
+		let body = &rest[8..(8 + size).min(rest.len())];
+		match id {
+			b"fmt " => format = Some(parse_format(body)?),
+			b"data" => {
+				return format.map(|f| (f, body)).ok_or("no fmt chunk before data".to_string())
+			},
+			_ => {},
+		}
+		// Chunks are padded to an even size.
+		rest = &rest[(8 + size + size % 2).min(rest.len())..];
+	}
+	Err("no data chunk".to_string())
+}
+
+fn parse_format(fmt: &[u8]) -> Result<PcmFormat, String> {
+	if fmt.len() < 16 {
+		return Err("truncated fmt chunk".to_string());
+	}
+	let u16_at = |i: usize| u16::from_le_bytes([fmt[i], fmt[i + 1]]);
+	let mut audio_format = u16_at(0);
+	if audio_format == EXTENSIBLE && fmt.len() >= 26 {
+		// The first two bytes of the sub-format GUID are the actual format.
+		audio_format = u16_at(24);
+	}
+	if audio_format != PCM {
+		return Err(format!("unsupported format {audio_format}, only integer PCM is supported"));
+	}
+	let format = PcmFormat {
+		sample_rate: u32::from_le_bytes([fmt[4], fmt[5], fmt[6], fmt[7]]),
+		channels: u16_at(2),
+		bits_per_sample: u16_at(14),
+	};
+	if format.sample_rate == 0 || format.block_align() == 0 {
+		return Err("invalid fmt chunk".to_string());
+	}
+	Ok(format)
+}
+
+/// Writes `data` as a WAV file with a canonical 44 bytes header.
+pub fn write(format: &PcmFormat, data: &[u8]) -> Vec<u8> {
+	let block_align = format.block_align() as u16;
+	let mut wav = Vec::with_capacity(HEADER_LEN + data.len());
+	wav.extend_from_slice(b"RIFF");
+	wav.extend_from_slice(&(36 + data.len() as u32).to_le_bytes());
+	wav.extend_from_slice(b"WAVEfmt ");
+	wav.extend_from_slice(&16u32.to_le_bytes());
+	wav.extend_from_slice(&PCM.to_le_bytes());
+	wav.extend_from_slice(&format.channels.to_le_bytes());
+	wav.extend_from_slice(&format.sample_rate.to_le_bytes());
+	wav.extend_from_slice(&(format.sample_rate * block_align as u32).to_le_bytes());
+	wav.extend_from_slice(&block_align.to_le_bytes());
+	wav.extend_from_slice(&format.bits_per_sample.to_le_bytes());
+	wav.extend_from_slice(b"data");
+	wav.extend_from_slice(&(data.len() as u32).to_le_bytes());
+	wav.extend_from_slice(data);
+	wav
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{parse, write, HEADER_LEN};
+	use crate::apis::audio::PcmFormat;
+
+	#[test]
+	fn test_write_parse() {
+		let format = PcmFormat { sample_rate: 16000, channels: 2, bits_per_sample: 16 };
+		let data = [1, 2, 3, 4, 5, 6, 7, 8];
+		let wav = write(&format, &data);
+		assert_eq!(wav.len(), HEADER_LEN + data.len());
+		assert_eq!(parse(&wav).unwrap(), (format, &data[..]));
+
+		// A LIST chunk of odd size before the data, which is padded.
+		let mut with_list = wav[..36].to_vec();
+		with_list.extend_from_slice(b"LIST\x03\x00\x00\x00abc\x00");
+		with_list.extend_from_slice(&wav[36..]);
+		assert_eq!(parse(&with_list).unwrap().1, data);
+
+		assert!(parse(b"RIFF\x00\x00\x00\x00WAVE").is_err());
+		assert!(parse(b"ID3\x04").is_err());
+	}
+}